@@ -2,6 +2,7 @@ wit_bindgen::generate!({ generate_all });
 
 use crate::exports::wasmcloud::messaging::handler::{Guest, BrokerMessage};
 use crate::wasi::logging::logging::*;
+use crate::wasmcloud::websocket::sender;
 
 struct WebSocketComponent;
 
@@ -45,6 +46,15 @@ impl Guest for WebSocketComponent {
             );
         }
 
+        // Echo the message back to the server as the same frame type
+        let sent = match String::from_utf8(msg.body) {
+            Ok(text) => sender::send_text(&text),
+            Err(e) => sender::send_binary(&e.into_bytes()),
+        };
+        if let Err(e) = sent {
+            log(Level::Warn, "", &format!("Failed to echo message: {}", e));
+        }
+
         // Successfully handled the message
         Ok(())
     }
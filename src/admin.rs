@@ -0,0 +1,224 @@
+//! Optional HTTP admin API for managing connections without NATS
+//!
+//! Enabled with the `rest-admin` feature and the `admin_port` provider
+//! setting. The server binds to localhost only.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use tower_http::trace::TraceLayer;
+use tracing::info;
+
+use crate::config::LinkConfig;
+use crate::provider::{ConnectionInfo, ProviderStats, WebSocketProvider};
+
+/// Build the admin API routes
+pub fn router(provider: WebSocketProvider) -> Router {
+    Router::new()
+        .route("/connections", get(list_connections))
+        .route("/connections/{source_id}", delete(disconnect))
+        .route("/connections/{source_id}/reconnect", post(reconnect))
+        .route("/connections/{source_id}/drain", post(drain))
+        .route("/connections/{source_id}/config", get(connection_config))
+        .route("/connections/{source_id}/diagnostics", get(diagnostics))
+        .route("/stats", get(stats))
+        .layer(TraceLayer::new_for_http())
+        .with_state(provider)
+}
+
+/// Serve the admin API on `127.0.0.1:<port>` until the process exits
+pub async fn serve(provider: WebSocketProvider, port: u16) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+    info!("Admin API listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(provider)).await?;
+    Ok(())
+}
+
+async fn list_connections(State(provider): State<WebSocketProvider>) -> Json<Vec<ConnectionInfo>> {
+    Json(provider.list_connections().await)
+}
+
+async fn disconnect(
+    State(provider): State<WebSocketProvider>,
+    Path(source_id): Path<String>,
+) -> StatusCode {
+    if provider.disconnect(&source_id).await {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn drain(
+    State(provider): State<WebSocketProvider>,
+    Path(source_id): Path<String>,
+) -> StatusCode {
+    if provider.drain(&source_id).await {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn reconnect(
+    State(provider): State<WebSocketProvider>,
+    Path(source_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if provider.connection_config(&source_id).await.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No connection for {}", source_id),
+        ));
+    }
+    provider
+        .reconnect(&source_id)
+        .await
+        .map(|_| StatusCode::ACCEPTED)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn connection_config(
+    State(provider): State<WebSocketProvider>,
+    Path(source_id): Path<String>,
+) -> Result<Json<LinkConfig>, StatusCode> {
+    provider
+        .connection_config(&source_id)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn diagnostics(
+    State(provider): State<WebSocketProvider>,
+    Path(source_id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    provider
+        .diagnostics(&source_id)
+        .await
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn stats(State(provider): State<WebSocketProvider>) -> Json<ProviderStats> {
+    Json(provider.stats().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// A provider with component `a` linked to a local port nothing listens on
+    async fn linked_provider() -> WebSocketProvider {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let link = LinkConfig::builder(format!("ws://127.0.0.1:{}", port))
+            .build()
+            .unwrap();
+        let provider = WebSocketProvider::default();
+        provider.start_connection("a", link).await.unwrap();
+        provider
+    }
+
+    async fn call(
+        provider: &WebSocketProvider,
+        method: Method,
+        uri: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = router(provider.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json = serde_json::from_slice(&body).unwrap_or_default();
+        (status, json)
+    }
+
+    #[tokio::test]
+    async fn list_connections() {
+        let provider = linked_provider().await;
+        let (status, json) = call(&provider, Method::GET, "/connections").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json[0]["source_id"], "a");
+        assert!(json[1].is_null());
+    }
+
+    #[tokio::test]
+    async fn connection_config() {
+        let provider = linked_provider().await;
+        let (status, json) = call(&provider, Method::GET, "/connections/a/config").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(json["websocket_url"]
+            .as_str()
+            .unwrap()
+            .starts_with("ws://127.0.0.1:"));
+    }
+
+    #[tokio::test]
+    async fn diagnostics() {
+        let provider = linked_provider().await;
+        let (status, json) = call(&provider, Method::GET, "/connections/a/diagnostics").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(json.is_array());
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let provider = linked_provider().await;
+        let (status, json) = call(&provider, Method::GET, "/stats").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["connections"], 1);
+        assert_eq!(json["failed"], 0);
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        let provider = linked_provider().await;
+        let (status, _) = call(&provider, Method::POST, "/connections/a/reconnect").await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(provider.active_connection_ids().await, ["a"]);
+    }
+
+    #[tokio::test]
+    async fn drain() {
+        let provider = linked_provider().await;
+        let (status, _) = call(&provider, Method::POST, "/connections/a/drain").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(provider.active_connection_ids().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn disconnect() {
+        let provider = linked_provider().await;
+        let (status, _) = call(&provider, Method::DELETE, "/connections/a").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(provider.active_connection_ids().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unknown_source_id_is_not_found() {
+        let provider = linked_provider().await;
+        for (method, uri) in [
+            (Method::DELETE, "/connections/b"),
+            (Method::POST, "/connections/b/reconnect"),
+            (Method::POST, "/connections/b/drain"),
+            (Method::GET, "/connections/b/config"),
+            (Method::GET, "/connections/b/diagnostics"),
+        ] {
+            let (status, _) = call(&provider, method.clone(), uri).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}", method, uri);
+        }
+    }
+}
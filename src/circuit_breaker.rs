@@ -0,0 +1,134 @@
+//! Circuit breaker that suspends reconnects to a persistently failing server
+
+use std::time::Duration;
+
+use tracing::warn;
+
+/// Circuit breaker state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Connection attempts proceed normally
+    Closed,
+    /// Too many consecutive failures; attempts are suspended
+    Open,
+    /// The suspension has elapsed; the next attempt is a probe
+    HalfOpen,
+}
+
+/// Counts consecutive connection failures and opens after a threshold
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    reset_timeout: Duration,
+    failures: u32,
+    state: CircuitState,
+}
+
+impl CircuitBreaker {
+    /// Create a breaker that opens after `threshold` consecutive failures
+    /// (0 to disable) and stays open for `reset_timeout`
+    pub fn new(threshold: u32, reset_timeout: Duration) -> Self {
+        Self {
+            threshold,
+            reset_timeout,
+            failures: 0,
+            state: CircuitState::Closed,
+        }
+    }
+
+    /// Record a successful connection, closing the breaker
+    pub fn record_success(&mut self) {
+        if self.state != CircuitState::Closed {
+            warn!("Circuit breaker closed: connection re-established");
+        }
+        self.failures = 0;
+        self.state = CircuitState::Closed;
+    }
+
+    /// Record a failed connection attempt
+    ///
+    /// Returns how long to suspend reconnects when this failure opens the
+    /// breaker. A failed probe while half-open reopens it immediately.
+    pub fn record_failure(&mut self) -> Option<Duration> {
+        self.failures = self.failures.saturating_add(1);
+        let open = match self.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => self.threshold > 0 && self.failures >= self.threshold,
+            CircuitState::Open => false,
+        };
+        if !open {
+            return None;
+        }
+
+        warn!(
+            "Circuit breaker open after {} consecutive failures, suspending reconnects for {:?}",
+            self.failures, self.reset_timeout
+        );
+        self.state = CircuitState::Open;
+        Some(self.reset_timeout)
+    }
+
+    /// Move an open breaker to half-open once its suspension has elapsed
+    pub fn half_open(&mut self) {
+        if self.state == CircuitState::Open {
+            warn!("Circuit breaker half-open: probing the server");
+            self.state = CircuitState::HalfOpen;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESET: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn opens_after_threshold_consecutive_failures() {
+        let mut breaker = CircuitBreaker::new(3, RESET);
+        assert_eq!(breaker.record_failure(), None);
+        assert_eq!(breaker.record_failure(), None);
+        assert_eq!(breaker.record_failure(), Some(RESET));
+        assert_eq!(breaker.state, CircuitState::Open);
+    }
+
+    #[test]
+    fn success_resets_the_failure_count() {
+        let mut breaker = CircuitBreaker::new(2, RESET);
+        assert_eq!(breaker.record_failure(), None);
+        breaker.record_success();
+        assert_eq!(breaker.record_failure(), None);
+        assert_eq!(breaker.state, CircuitState::Closed);
+    }
+
+    #[test]
+    fn failed_probe_reopens_immediately() {
+        let mut breaker = CircuitBreaker::new(2, RESET);
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.half_open();
+        assert_eq!(breaker.state, CircuitState::HalfOpen);
+        assert_eq!(breaker.record_failure(), Some(RESET));
+        assert_eq!(breaker.state, CircuitState::Open);
+    }
+
+    #[test]
+    fn successful_probe_closes() {
+        let mut breaker = CircuitBreaker::new(1, RESET);
+        breaker.record_failure();
+        breaker.half_open();
+        breaker.record_success();
+        assert_eq!(breaker.state, CircuitState::Closed);
+        assert_eq!(breaker.record_failure(), Some(RESET));
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let mut breaker = CircuitBreaker::new(0, RESET);
+        for _ in 0..100 {
+            assert_eq!(breaker.record_failure(), None);
+        }
+        breaker.half_open();
+        assert_eq!(breaker.state, CircuitState::Closed);
+    }
+}
@@ -0,0 +1,111 @@
+//! Optional compression of forwarded message bodies
+//!
+//! Compressed bodies are plain zstd or gzip frames, so consumers can detect
+//! them by their magic bytes (`28 B5 2F FD` for zstd, `1F 8B` for gzip).
+
+use crate::config::{Compression, CompressionAlgorithm};
+
+/// Compress `data` when it is larger than the configured threshold
+pub fn maybe_compress(data: Vec<u8>, compression: &Compression) -> anyhow::Result<Vec<u8>> {
+    if data.len() <= compression.threshold_bytes {
+        return Ok(data);
+    }
+    compress(&data, compression.algorithm)
+}
+
+/// Decompress a zstd or gzip payload
+#[cfg(feature = "compression")]
+pub fn decompress(data: &[u8], algorithm: CompressionAlgorithm) -> anyhow::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut decompressed = Vec::new();
+    match algorithm {
+        CompressionAlgorithm::Zstd => decompressed = zstd::decode_all(data)?,
+        CompressionAlgorithm::Gzip => {
+            flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed)?;
+        }
+    }
+    Ok(decompressed)
+}
+
+/// Decompress a zstd or gzip payload
+#[cfg(not(feature = "compression"))]
+pub fn decompress(_data: &[u8], algorithm: CompressionAlgorithm) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!(
+        "{:?} decompression requires the provider to be built with the `compression` feature",
+        algorithm
+    )
+}
+
+#[cfg(feature = "compression")]
+fn compress(data: &[u8], algorithm: CompressionAlgorithm) -> anyhow::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let compressed = match algorithm {
+        CompressionAlgorithm::Zstd => zstd::encode_all(data, 0)?,
+        CompressionAlgorithm::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+    };
+    Ok(compressed)
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_data: &[u8], algorithm: CompressionAlgorithm) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!(
+        "{:?} compression requires the provider to be built with the `compression` feature",
+        algorithm
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compression(algorithm: CompressionAlgorithm) -> Compression {
+        Compression {
+            algorithm,
+            threshold_bytes: 16,
+        }
+    }
+
+    #[test]
+    fn small_bodies_are_left_alone() {
+        let body = b"short".to_vec();
+        let out = maybe_compress(body.clone(), &compression(CompressionAlgorithm::Zstd)).unwrap();
+        assert_eq!(out, body);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn large_bodies_round_trip() {
+        let body = br#"{"price": 1.5, "symbol": "BTC"}"#.repeat(10);
+        for (algorithm, magic) in [
+            (CompressionAlgorithm::Zstd, &[0x28, 0xb5, 0x2f, 0xfd][..]),
+            (CompressionAlgorithm::Gzip, &[0x1f, 0x8b][..]),
+        ] {
+            let out = maybe_compress(body.clone(), &compression(algorithm)).unwrap();
+            assert!(out.starts_with(magic));
+            assert!(out.len() < body.len());
+            assert_eq!(decompress(&out, algorithm).unwrap(), body);
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decompress_rejects_corrupt_data() {
+        assert!(decompress(b"not compressed", CompressionAlgorithm::Zstd).is_err());
+        assert!(decompress(b"not compressed", CompressionAlgorithm::Gzip).is_err());
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn large_bodies_need_the_feature() {
+        let body = vec![0u8; 64];
+        let err = maybe_compress(body, &compression(CompressionAlgorithm::Gzip)).unwrap_err();
+        assert!(err.to_string().contains("`compression` feature"));
+    }
+}
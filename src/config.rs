@@ -1,15 +1,34 @@
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
+use tungstenite::http::{HeaderName, HeaderValue};
 use url::Url;
 
+use crate::error::ProviderError;
+use crate::message::MessageType;
+use crate::pipeline::{self, MessageFilter, Stage};
+use crate::policy::{LinkPolicy, LINK_POLICY_ENV};
+use crate::proxy::PROXY_SCHEMES;
+use crate::transform::TransformConfig;
+
 /// Configuration for the WebSocket provider
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ProviderConfig {
     values: HashMap<String, String>,
 }
 
+impl Hash for ProviderConfig {
+    /// Hash entries in key order so equal configs hash equally
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut entries: Vec<_> = self.values.iter().collect();
+        entries.sort();
+        entries.hash(state);
+    }
+}
+
 impl From<&HashMap<String, String>> for ProviderConfig {
     /// Construct configuration struct from the passed config values.
     fn from(values: &HashMap<String, String>) -> ProviderConfig {
@@ -19,12 +38,450 @@ impl From<&HashMap<String, String>> for ProviderConfig {
     }
 }
 
+/// What to do with a new link when its upstream host is at the connection limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostLimitPolicy {
+    /// Reject the link
+    Reject,
+    /// Accept the link but wait for a free slot before connecting
+    Queue,
+}
+
+/// Builds a [`ProviderConfig`] key by key, validating it on [`build`](Self::build)
+#[derive(Debug, Clone, Default)]
+pub struct ProviderConfigBuilder {
+    values: HashMap<String, String>,
+}
+
+impl ProviderConfigBuilder {
+    /// Set a provider configuration key, e.g. `set("subject_prefix", "tenant123")`
+    pub fn set(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.values.insert(key.into(), value.to_string());
+        self
+    }
+
+    /// Validate the values and build the config
+    pub fn build(&self) -> anyhow::Result<ProviderConfig> {
+        let config = ProviderConfig::from(&self.values);
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl ProviderConfig {
+    /// Start building a provider config
+    pub fn builder() -> ProviderConfigBuilder {
+        ProviderConfigBuilder::default()
+    }
+
+    /// Check every provider-level value, failing on the first invalid one
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.max_connections_per_host()?;
+        self.admin_port()?;
+        self.link_policy()?;
+        self.host_limit_policy()?;
+        self.subject_prefix()?;
+        Ok(())
+    }
+
+    /// Maximum concurrent connections to a single upstream host, if limited
+    pub fn max_connections_per_host(&self) -> anyhow::Result<Option<usize>> {
+        self.values
+            .get("max_connections_per_host")
+            .map(|v| {
+                v.parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid max_connections_per_host: {}", e))
+            })
+            .transpose()
+    }
+
+    /// Port for the HTTP admin API, if enabled
+    pub fn admin_port(&self) -> anyhow::Result<Option<u16>> {
+        self.values
+            .get("admin_port")
+            .map(|v| {
+                v.parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid admin_port: {}", e))
+            })
+            .transpose()
+    }
+
+    /// Policy constraining link configs, from `link_policy` or the environment
+    pub fn link_policy(&self) -> anyhow::Result<LinkPolicy> {
+        match self.values.get("link_policy") {
+            Some(json) => LinkPolicy::from_json_str(json),
+            None => match std::env::var(LINK_POLICY_ENV) {
+                Ok(json) => LinkPolicy::from_json_str(&json),
+                Err(_) => Ok(LinkPolicy::default()),
+            },
+        }
+    }
+
+    /// Policy applied when a host is at `max_connections_per_host`
+    pub fn host_limit_policy(&self) -> anyhow::Result<HostLimitPolicy> {
+        match self.values.get("host_limit_policy").map(String::as_str) {
+            None | Some("reject") => Ok(HostLimitPolicy::Reject),
+            Some("queue") => Ok(HostLimitPolicy::Queue),
+            Some(other) => anyhow::bail!("Invalid host_limit_policy: {}", other),
+        }
+    }
+
+    /// Prefix prepended to every forwarded subject, without a trailing `.`
+    pub fn subject_prefix(&self) -> anyhow::Result<Option<String>> {
+        let Some(prefix) = self.values.get("subject_prefix") else {
+            return Ok(None);
+        };
+        let prefix = prefix.trim_end_matches('.');
+        validate_subject(prefix).map_err(|e| anyhow::anyhow!("Invalid subject_prefix: {}", e))?;
+        Ok(Some(prefix.to_string()))
+    }
+
+    /// Parse configuration values from a YAML document
+    ///
+    /// Anchors, aliases and `<<` merge keys are resolved before flattening.
+    pub fn from_yaml_str(s: &str) -> anyhow::Result<ProviderConfig> {
+        let mut document: serde_norway::Value = serde_norway::from_str(s)?;
+        document.apply_merge()?;
+        Ok(Self::from_document(serde_norway::from_value(document)?))
+    }
+
+    /// Parse configuration values from a TOML document
+    pub fn from_toml_str(s: &str) -> anyhow::Result<ProviderConfig> {
+        Ok(Self::from_document(toml::from_str(s)?))
+    }
+
+    /// Parse configuration values from a JSON document
+    pub fn from_json_str(s: &str) -> anyhow::Result<ProviderConfig> {
+        Ok(Self::from_document(serde_json::from_str(s)?))
+    }
+
+    /// Load configuration from a file, with `values` taking precedence
+    ///
+    /// The format is picked from the file extension: `.yaml`/`.yml`, `.toml` or `.json`.
+    pub fn from_file_and_values(
+        path: impl AsRef<Path>,
+        values: &HashMap<String, String>,
+    ) -> anyhow::Result<ProviderConfig> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let parse: fn(&str) -> anyhow::Result<ProviderConfig> = match extension {
+            "yaml" | "yml" => Self::from_yaml_str,
+            "toml" => Self::from_toml_str,
+            "json" => Self::from_json_str,
+            other => anyhow::bail!("Unsupported config format: .{} ({})", other, path.display()),
+        };
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config file {}: {}", path.display(), e))?;
+        let mut config = parse(&contents)?;
+
+        config
+            .values
+            .extend(values.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Ok(config)
+    }
+
+    /// Flatten a parsed document into string values, as link and init config are
+    fn from_document(document: HashMap<String, serde_json::Value>) -> ProviderConfig {
+        let values = document
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(s) => (key, s),
+                other => (key, other.to_string()),
+            })
+            .collect();
+        ProviderConfig { values }
+    }
+}
+
+/// Check that a subject is a valid, literal NATS subject
+///
+/// Subjects must be non-empty, contain no whitespace, have no empty tokens
+/// and use no wildcards.
+pub fn validate_subject(subject: &str) -> anyhow::Result<()> {
+    if subject.is_empty() {
+        anyhow::bail!("subject is empty");
+    }
+    if subject.chars().any(char::is_whitespace) {
+        anyhow::bail!("subject `{}` contains whitespace", subject);
+    }
+    for token in subject.split('.') {
+        match token {
+            "" => anyhow::bail!("subject `{}` has an empty token", subject),
+            "*" | ">" => anyhow::bail!("subject `{}` contains a wildcard", subject),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Expand `{scheme}`, `{host}`, `{port}` and `{path}` in a subject template
+///
+/// `{path}` joins the URL's path segments with dots, so `/feeds/btc` becomes
+/// `feeds.btc`. Templates without placeholders are returned unchanged.
+fn expand_subject_template(template: &str, url: &Url) -> anyhow::Result<String> {
+    substitute(template, |name| {
+        url_placeholder(url, name)
+            .ok_or_else(|| anyhow::anyhow!("unknown placeholder `{{{}}}` in `{}`", name, template))
+    })
+}
+
+/// Expand the URL placeholders in a `subject_template`, keeping the
+/// per-message `{message_type}` and `{json.<field>}` placeholders for
+/// [`LinkConfig::message_subject`]
+fn expand_message_template(template: &str, url: &Url) -> anyhow::Result<String> {
+    substitute(template, |name| match url_placeholder(url, name) {
+        Some(value) => Ok(value),
+        None if name == "message_type" => Ok(format!("{{{}}}", name)),
+        None if name
+            .strip_prefix("json.")
+            .is_some_and(|path| !path.is_empty()) =>
+        {
+            Ok(format!("{{{}}}", name))
+        }
+        None => anyhow::bail!("unknown placeholder `{{{}}}` in `{}`", name, template),
+    })
+}
+
+/// Value of a `{scheme}`, `{host}`, `{port}` or `{path}` placeholder
+fn url_placeholder(url: &Url, name: &str) -> Option<String> {
+    let value = match name {
+        "scheme" => url.scheme().to_string(),
+        "host" => url.host_str().unwrap_or_default().to_string(),
+        "port" => url
+            .port_or_known_default()
+            .map(|port| port.to_string())
+            .unwrap_or_default(),
+        "path" => url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    .filter(|segment| !segment.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(".")
+            })
+            .unwrap_or_default(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Replace every `{name}` in `template` with `value(name)`
+fn substitute(
+    template: &str,
+    mut value: impl FnMut(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<String> {
+    let mut subject = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        subject.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("unclosed placeholder in `{}`", template))?;
+        subject.push_str(&value(&rest[start + 1..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    subject.push_str(rest);
+    Ok(subject)
+}
+
+/// Look up a dot-separated field in a JSON payload as a subject token
+///
+/// Only strings, numbers and booleans can fill a placeholder.
+fn json_field(payload: &serde_json::Value, path: &str) -> Option<String> {
+    let value = path
+        .split('.')
+        .try_fold(payload, |value, field| value.get(field))?;
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Parse an optional config value, falling back to `default` when it is absent
+fn parse_or<T>(config: &HashMap<String, String>, key: &str, default: T) -> anyhow::Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    Ok(parse_opt(config, key)?.unwrap_or(default))
+}
+
+/// Parse an optional config value
+///
+/// Values that are present but malformed are rejected with an error naming
+/// the key, rather than silently ignored.
+fn parse_opt<T>(config: &HashMap<String, String>, key: &str) -> anyhow::Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    config
+        .get(key)
+        .map(|value| {
+            value.trim().parse().map_err(|e| {
+                ProviderError::ConfigError(format!("{}: invalid value {:?}: {}", key, value, e))
+                    .into()
+            })
+        })
+        .transpose()
+}
+
+/// Collect handshake headers from `header.<name>`/`header_<name>` keys and a
+/// `headers` JSON map
+///
+/// Per-header keys take precedence over entries in `headers`. Names and
+/// values are validated as HTTP headers.
+fn parse_headers(config: &HashMap<String, String>) -> anyhow::Result<HashMap<String, String>> {
+    let mut headers: HashMap<String, String> = match config.get("headers") {
+        Some(json) => serde_json::from_str(json).map_err(|e| {
+            ProviderError::ConfigError(format!("headers must be a JSON map of strings: {}", e))
+        })?,
+        None => HashMap::new(),
+    };
+    for (key, value) in config {
+        if let Some(name) = key
+            .strip_prefix("header.")
+            .or_else(|| key.strip_prefix("header_"))
+        {
+            headers.insert(name.to_string(), value.clone());
+        }
+    }
+
+    for (name, value) in &headers {
+        HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| ProviderError::ConfigError(format!("invalid header name: {:?}", name)))?;
+        HeaderValue::from_str(value).map_err(|_| {
+            ProviderError::ConfigError(format!("invalid value for header {}", name))
+        })?;
+    }
+    Ok(headers)
+}
+
+/// Resolve a `${VAR}` reference to the environment variable's value
+///
+/// Values that are not a reference are returned unchanged, so secrets can
+/// stay out of link config.
+fn resolve_env_reference(value: &str) -> anyhow::Result<String> {
+    match value
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+    {
+        Some(var) => std::env::var(var).map_err(|_| {
+            ProviderError::ConfigError(format!("environment variable {} is not set", var)).into()
+        }),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Serialize a header map with its values replaced, since they often hold credentials
+fn serialize_redacted<S: serde::Serializer>(
+    headers: &HashMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(headers.keys().map(|name| (name, "<redacted>")))
+}
+
+/// Serialize a proxy URL with its password replaced
+fn serialize_redacted_url<S: serde::Serializer>(
+    url: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let redacted = url.as_deref().map(|url| match Url::parse(url) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("redacted"));
+            url.to_string()
+        }
+        _ => url.to_string(),
+    });
+    serializer.serialize_some(&redacted)
+}
+
+/// What to do when the server violates the WebSocket protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProtocolViolationPolicy {
+    /// Treat it like any other connection error and reconnect
+    Reconnect,
+    /// Stop the client without reconnecting
+    Fail,
+}
+
+/// What to do with data messages above `max_messages_per_sec`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitPolicy {
+    /// Drop the message
+    Drop,
+    /// Wait for the rate to allow it, pausing reads from the server
+    Delay,
+}
+
+/// Compression algorithm applied to forwarded message bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    Zstd,
+    Gzip,
+}
+
+impl std::str::FromStr for CompressionAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "zstd" => Ok(Self::Zstd),
+            "gzip" => Ok(Self::Gzip),
+            other => anyhow::bail!("Unsupported compression algorithm: {}", other),
+        }
+    }
+}
+
+/// TLS protocol version a link may be constrained to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl std::str::FromStr for TlsVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().trim_start_matches("tls").trim() {
+            "1.2" => Ok(Self::Tls12),
+            "1.3" => Ok(Self::Tls13),
+            other => anyhow::bail!("Unsupported TLS version: {} (expected 1.2 or 1.3)", other),
+        }
+    }
+}
+
+/// Compression settings for forwarded message bodies
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Compression {
+    /// Algorithm used to compress the body
+    pub algorithm: CompressionAlgorithm,
+
+    /// Bodies larger than this many bytes are compressed
+    pub threshold_bytes: usize,
+}
+
 /// Link-specific configuration for WebSocket connections
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LinkConfig {
     /// WebSocket server URL to connect to
     pub websocket_url: String,
 
+    /// Fallback URLs tried in order after `websocket_url` keeps failing
+    pub websocket_urls: Vec<String>,
+
     /// Maximum reconnection attempts (0 for infinite)
     pub max_reconnect_attempts: u32,
 
@@ -34,11 +491,198 @@ pub struct LinkConfig {
     /// Maximum reconnection delay in milliseconds
     pub max_reconnect_delay_ms: u64,
 
-    /// Maximum message size in bytes
+    /// Random jitter applied to each backoff delay, as a percentage (0-100)
+    pub reconnect_jitter_pct: u8,
+
+    /// Upper bound in seconds of a random extra delay added to each reconnect
+    pub reconnect_spread_secs: u64,
+
+    /// Consecutive connection failures before reconnects are suspended (0 to disable)
+    pub circuit_open_threshold: u32,
+
+    /// Seconds reconnects stay suspended once the circuit breaker opens
+    pub circuit_reset_secs: u64,
+
+    /// Messages that may await delivery to the component before new ones are dropped
+    pub message_buffer_size: usize,
+
+    /// Seconds to wait for in-flight messages when a link is drained or deleted
+    pub drain_timeout_secs: u64,
+
+    /// Maximum message size in bytes (0 for unlimited)
     pub max_message_size: usize,
+
+    /// Timeout in seconds for establishing the TCP connection
+    pub connect_timeout_secs: u64,
+
+    /// Timeout in milliseconds for the WebSocket upgrade after TCP connects
+    pub handshake_timeout_ms: u64,
+
+    /// Forward an end-of-stream marker when the connection closes
+    pub emit_eos_message: bool,
+
+    /// Forward gap/resume markers around reconnect windows
+    pub emit_gap_markers: bool,
+
+    /// Attach connection metadata (subprotocol, server IP, epoch, uptime) to data messages
+    pub enrich_metadata: bool,
+
+    /// Whether a protocol violation triggers a reconnect or fails the link
+    pub on_protocol_violation: ProtocolViolationPolicy,
+
+    /// Data messages collected into one forwarded JSON array (0 to disable batching)
+    pub batch_size: usize,
+
+    /// Milliseconds a partial batch waits before it is forwarded anyway
+    pub batch_timeout_ms: u64,
+
+    /// Answer `wasmcloud:messaging/consumer` requests with the server's next message
+    pub request_reply_mode: bool,
+
+    /// Milliseconds to wait for a reply when the request does not set a timeout
+    pub reply_timeout_ms: u64,
+
+    /// Maximum data messages forwarded per second (0 for unlimited)
+    pub max_messages_per_sec: u32,
+
+    /// Whether messages above `max_messages_per_sec` are dropped or delayed
+    pub rate_limit_policy: RateLimitPolicy,
+
+    /// Text payload sent to the server after connecting (e.g. a subscribe request)
+    pub on_connect_send: Option<String>,
+
+    /// Interval in seconds for resending `on_connect_send` while connected (0 to disable)
+    pub resubscribe_interval_secs: u64,
+
+    /// Seconds without activity before the connection is considered dead (0 to disable)
+    pub idle_timeout_secs: u64,
+
+    /// Count received Ping/Pong frames as activity for the idle timer
+    pub control_frames_as_activity: bool,
+
+    /// Interval in seconds between Pings sent to the server (0 to disable)
+    pub ping_interval_secs: u64,
+
+    /// Seconds to wait for a Pong after sending a Ping before reconnecting
+    pub pong_timeout_secs: u64,
+
+    /// Split text frames on this delimiter and forward each segment (e.g. `\n` for NDJSON)
+    pub split_delimiter: Option<String>,
+
+    /// Maximum segments per split frame before the whole frame is dropped (0 for unlimited)
+    pub max_segments_per_frame: usize,
+
+    /// Transformation stages run in order on each data message, including those from `transform`
+    pub pipeline: Vec<Stage>,
+
+    /// Forward only JSON messages matching this predicate
+    pub message_filter: Option<MessageFilter>,
+
+    /// Drop non-JSON messages while `message_filter` is set, instead of passing them through
+    pub message_filter_drop_non_json: bool,
+
+    /// Probe the endpoint before accepting the link, rejecting it on failure
+    pub probe_before_link: bool,
+
+    /// Subject for data messages, instead of `websocket.<url>`
+    pub subject: Option<String>,
+
+    /// Subject for text data messages, overriding `subject`
+    pub text_subject: Option<String>,
+
+    /// Subject for binary data messages, overriding `subject`
+    pub binary_subject: Option<String>,
+
+    /// Per-message subject with `{message_type}` and `{json.<field>}`
+    /// placeholders, overriding the static data subjects
+    pub subject_template: Option<String>,
+
+    /// Subject for periodic connection status heartbeats (disabled when unset)
+    pub heartbeat_subject: Option<String>,
+
+    /// Interval in seconds between heartbeats
+    pub heartbeat_interval_secs: u64,
+
+    /// Subject for connected/disconnected/reconnecting lifecycle events (disabled when unset)
+    pub status_subject: Option<String>,
+
+    /// Subject that messages the component failed to accept are re-sent on (disabled when unset)
+    pub dead_letter_subject: Option<String>,
+
+    /// Compress large message bodies before forwarding (requires the `compression` feature)
+    pub compression: Option<Compression>,
+
+    /// Lowest TLS version accepted for wss:// connections
+    pub tls_min_version: Option<TlsVersion>,
+
+    /// Highest TLS version offered for wss:// connections
+    pub tls_max_version: Option<TlsVersion>,
+
+    /// PEM CA certificates trusted in addition to the WebPKI roots
+    pub ca_cert_path: Option<String>,
+
+    /// PEM certificate chain presented to the server for mutual TLS
+    pub client_cert_path: Option<String>,
+
+    /// PEM private key matching `client_cert_path`
+    pub client_key_path: Option<String>,
+
+    /// Extra HTTP headers sent with the opening handshake (values are redacted when serialized)
+    #[serde(serialize_with = "serialize_redacted")]
+    pub headers: HashMap<String, String>,
+
+    /// Subprotocols offered in `Sec-WebSocket-Protocol`, in order of preference
+    pub subprotocols: Vec<String>,
+
+    /// Bearer token sent as `Authorization: Bearer <token>` with the handshake
+    #[serde(skip_serializing)]
+    pub auth_token: Option<String>,
+
+    /// HTTP or SOCKS5 proxy that connections are tunnelled through (password redacted when serialized)
+    #[serde(serialize_with = "serialize_redacted_url")]
+    pub proxy_url: Option<String>,
+
+    /// Try a ws:// URL as wss:// first, falling back to plaintext if TLS fails
+    pub auto_upgrade_tls: bool,
+
+    /// Never connect in plaintext
+    pub require_tls: bool,
+}
+
+/// Builder for [`LinkConfig`] in code, using the same keys as link configuration
+///
+/// Values go through [`LinkConfig::from_values`], so a built config is
+/// validated exactly like one received from a link, and new keys never
+/// break existing callers.
+#[derive(Debug, Clone, Default)]
+pub struct LinkConfigBuilder {
+    values: HashMap<String, String>,
+}
+
+impl LinkConfigBuilder {
+    /// Start a config for the given WebSocket URL
+    pub fn new(websocket_url: impl Into<String>) -> Self {
+        Self::default().set("websocket_url", websocket_url.into())
+    }
+
+    /// Set a link configuration key, e.g. `set("max_message_size", 65536)`
+    pub fn set(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.values.insert(key.into(), value.to_string());
+        self
+    }
+
+    /// Validate the values and build the config
+    pub fn build(&self) -> anyhow::Result<LinkConfig> {
+        LinkConfig::from_values(&self.values)
+    }
 }
 
 impl LinkConfig {
+    /// Start building a config for the given WebSocket URL
+    pub fn builder(websocket_url: impl Into<String>) -> LinkConfigBuilder {
+        LinkConfigBuilder::new(websocket_url)
+    }
+
     /// Create from link configuration values
     pub fn from_values(config: &HashMap<String, String>) -> anyhow::Result<Self> {
         let websocket_url = config
@@ -52,32 +696,325 @@ impl LinkConfig {
             anyhow::bail!("WebSocket URL must use ws:// or wss:// scheme");
         }
 
-        let max_reconnect_attempts = config
-            .get("max_reconnect_attempts")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(0);
+        let websocket_urls: Vec<String> = config
+            .get("websocket_urls")
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        for fallback in &websocket_urls {
+            let valid =
+                Url::parse(fallback).is_ok_and(|url| url.scheme() == "ws" || url.scheme() == "wss");
+            if !valid {
+                return Err(ProviderError::ConfigError(format!(
+                    "websocket_urls: {:?} is not a ws:// or wss:// URL",
+                    fallback
+                ))
+                .into());
+            }
+        }
+
+        let headers = parse_headers(config)?;
+
+        let subprotocols: Vec<String> = config
+            .get("subprotocols")
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|protocol| !protocol.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(protocol) = subprotocols
+            .iter()
+            .find(|protocol| HeaderValue::from_str(protocol).is_err() || protocol.contains(' '))
+        {
+            return Err(
+                ProviderError::ConfigError(format!("invalid subprotocol: {:?}", protocol)).into(),
+            );
+        }
+
+        let auth_token = config
+            .get("auth_token")
+            .map(|token| resolve_env_reference(token))
+            .transpose()?;
+        if let Some(token) = &auth_token {
+            HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|_| {
+                ProviderError::ConfigError("auth_token contains invalid characters".into())
+            })?;
+        }
+
+        let proxy_url = config.get("proxy_url").cloned();
+        if let Some(proxy_url) = &proxy_url {
+            let proxy = Url::parse(proxy_url)
+                .map_err(|e| ProviderError::ConfigError(format!("invalid proxy_url: {}", e)))?;
+            if !PROXY_SCHEMES.contains(&proxy.scheme()) || proxy.host_str().is_none() {
+                return Err(ProviderError::ConfigError(format!(
+                    "proxy_url must be an http://, socks5:// or socks5h:// URL with a host, got {}://",
+                    proxy.scheme()
+                ))
+                .into());
+            }
+        }
+
+        let auto_upgrade_tls = parse_or(config, "auto_upgrade_tls", false)?;
+
+        let require_tls = parse_or(config, "require_tls", false)?;
+
+        if require_tls && !auto_upgrade_tls {
+            let fallbacks = websocket_urls.iter().map(String::as_str);
+            if let Some(plaintext) = std::iter::once(websocket_url.as_str())
+                .chain(fallbacks)
+                .find(|url| url.starts_with("ws://"))
+            {
+                anyhow::bail!(
+                    "require_tls forbids plaintext ws:// URLs ({}); use wss:// or enable auto_upgrade_tls",
+                    plaintext
+                );
+            }
+        }
+
+        let max_reconnect_attempts = parse_or(config, "max_reconnect_attempts", 0)?;
 
-        let initial_reconnect_delay_ms = config
-            .get("initial_reconnect_delay_ms")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(1000);
+        let initial_reconnect_delay_ms = parse_or(config, "initial_reconnect_delay_ms", 1000)?;
 
-        let max_reconnect_delay_ms = config
-            .get("max_reconnect_delay_ms")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(60000);
+        let max_reconnect_delay_ms = parse_or(config, "max_reconnect_delay_ms", 60000)?;
+        if max_reconnect_delay_ms < initial_reconnect_delay_ms {
+            return Err(ProviderError::ConfigError(format!(
+                "max_reconnect_delay_ms ({}) must not be less than initial_reconnect_delay_ms ({})",
+                max_reconnect_delay_ms, initial_reconnect_delay_ms
+            ))
+            .into());
+        }
+
+        let reconnect_jitter_pct = parse_or(config, "reconnect_jitter_pct", 20)?;
+        if reconnect_jitter_pct > 100 {
+            return Err(ProviderError::ConfigError(
+                "reconnect_jitter_pct must be between 0 and 100".into(),
+            )
+            .into());
+        }
+
+        let reconnect_spread_secs = parse_or(config, "reconnect_spread_secs", 0)?;
+
+        let circuit_open_threshold = parse_or(config, "circuit_open_threshold", 5)?;
+
+        let circuit_reset_secs = parse_or(config, "circuit_reset_secs", 60)?;
+
+        let message_buffer_size = parse_or(config, "message_buffer_size", 1024)?;
+        if message_buffer_size == 0 {
+            anyhow::bail!("message_buffer_size must be greater than 0");
+        }
+
+        let drain_timeout_secs = parse_or(config, "drain_timeout_secs", 5)?;
+
+        let max_message_size = parse_or(config, "max_message_size", 1024 * 1024)?;
 
-        let max_message_size = config
-            .get("max_message_size")
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(1024 * 1024);
+        let connect_timeout_secs = parse_or(config, "connect_timeout_secs", 10)?;
+        if connect_timeout_secs == 0 {
+            anyhow::bail!("connect_timeout_secs must be greater than 0");
+        }
+
+        let handshake_timeout_ms = parse_or(config, "handshake_timeout_ms", 5000)?;
+
+        let emit_eos_message = parse_or(config, "emit_eos_message", false)?;
+
+        let emit_gap_markers = parse_or(config, "emit_gap_markers", false)?;
+
+        let enrich_metadata = parse_or(config, "enrich_metadata", false)?;
+
+        let on_protocol_violation = match config.get("on_protocol_violation").map(String::as_str) {
+            None | Some("reconnect") => ProtocolViolationPolicy::Reconnect,
+            Some("fail") => ProtocolViolationPolicy::Fail,
+            Some(other) => anyhow::bail!("Invalid on_protocol_violation: {}", other),
+        };
+
+        let batch_size = parse_or(config, "batch_size", 0)?;
+        let batch_timeout_ms = parse_or(config, "batch_timeout_ms", 100)?;
+        if batch_size > 0 && batch_timeout_ms == 0 {
+            return Err(ProviderError::ConfigError(
+                "batch_timeout_ms must be greater than 0 when batching".into(),
+            )
+            .into());
+        }
+
+        let request_reply_mode = parse_or(config, "request_reply_mode", false)?;
+        let reply_timeout_ms = parse_or(config, "reply_timeout_ms", 5000)?;
+        if request_reply_mode && reply_timeout_ms == 0 {
+            return Err(ProviderError::ConfigError(
+                "reply_timeout_ms must be greater than 0 in request_reply_mode".into(),
+            )
+            .into());
+        }
+
+        let max_messages_per_sec = parse_or(config, "max_messages_per_sec", 0)?;
+
+        let rate_limit_policy = match config.get("rate_limit_policy").map(String::as_str) {
+            None | Some("drop") => RateLimitPolicy::Drop,
+            Some("delay") => RateLimitPolicy::Delay,
+            Some(other) => anyhow::bail!("Invalid rate_limit_policy: {}", other),
+        };
+
+        let on_connect_send = config.get("on_connect_send").cloned();
+
+        let resubscribe_interval_secs = parse_or(config, "resubscribe_interval_secs", 0)?;
+
+        let idle_timeout_secs = parse_or(config, "idle_timeout_secs", 0)?;
+
+        let control_frames_as_activity = parse_or(config, "control_frames_as_activity", false)?;
+
+        let ping_interval_secs = parse_or(config, "ping_interval_secs", 0)?;
+
+        let pong_timeout_secs = parse_or(config, "pong_timeout_secs", 10)?;
+        if ping_interval_secs > 0 && pong_timeout_secs == 0 {
+            anyhow::bail!("pong_timeout_secs must be greater than 0");
+        }
+
+        // Allow escaped newlines since config values are plain strings
+        let split_delimiter = config
+            .get("split_delimiter")
+            .filter(|v| !v.is_empty())
+            .map(|v| v.replace("\\n", "\n").replace("\\r", "\r"));
+
+        let max_segments_per_frame = parse_or(config, "max_segments_per_frame", 10_000)?;
+
+        let mut pipeline = match config.get("pipeline") {
+            Some(json) => pipeline::parse(json)?,
+            None => Vec::new(),
+        };
+        if let Some(json) = config.get("transform") {
+            pipeline.extend(TransformConfig::parse(json)?.stages());
+        }
+
+        let message_filter = parse_opt(config, "message_filter")?;
+        let message_filter_drop_non_json = parse_or(config, "message_filter_drop_non_json", false)?;
+
+        let probe_before_link = parse_or(config, "probe_before_link", false)?;
+
+        // Subjects may be templated on the URL, so expand before validating
+        let subject_key = |key: &str| -> anyhow::Result<Option<String>> {
+            config
+                .get(key)
+                .map(|template| {
+                    expand_subject_template(template, &url)
+                        .and_then(|subject| validate_subject(&subject).map(|_| subject))
+                        .map_err(|e| ProviderError::ConfigError(format!("{}: {}", key, e)).into())
+                })
+                .transpose()
+        };
+        let subject = subject_key("subject")?;
+        let text_subject = subject_key("text_subject")?;
+        let binary_subject = subject_key("binary_subject")?;
+        let subject_template = config
+            .get("subject_template")
+            .map(|template| {
+                expand_message_template(template, &url)
+                    .and_then(|subject| validate_subject(&subject).map(|_| subject))
+                    .map_err(|e| ProviderError::ConfigError(format!("subject_template: {}", e)))
+            })
+            .transpose()?;
+        let status_subject = subject_key("status_subject")?;
+        let dead_letter_subject = subject_key("dead_letter_subject")?;
+
+        let heartbeat_subject = subject_key("heartbeat_subject")?;
+
+        let heartbeat_interval_secs = parse_or(config, "heartbeat_interval_secs", 30)?;
+
+        let compression = match parse_opt(config, "compression")? {
+            Some(algorithm) => Some(Compression {
+                algorithm,
+                threshold_bytes: parse_or(config, "compression_threshold_bytes", 4096)?,
+            }),
+            None => None,
+        };
+
+        if compression.is_some() && !cfg!(feature = "compression") {
+            anyhow::bail!(
+                "compression requires the provider to be built with the `compression` feature"
+            );
+        }
+
+        let tls_min_version = parse_opt(config, "tls_min_version")?;
+        let tls_max_version = parse_opt(config, "tls_max_version")?;
+        if let (Some(min), Some(max)) = (tls_min_version, tls_max_version) {
+            if min > max {
+                anyhow::bail!("tls_min_version must not be greater than tls_max_version");
+            }
+        }
+
+        let ca_cert_path = config.get("ca_cert_path").cloned();
+
+        let client_cert_path = config.get("client_cert_path").cloned();
+        let client_key_path = config.get("client_key_path").cloned();
+        if client_cert_path.is_some() != client_key_path.is_some() {
+            return Err(ProviderError::ConfigError(
+                "client_cert_path and client_key_path must be set together".into(),
+            )
+            .into());
+        }
 
         Ok(Self {
             websocket_url,
+            websocket_urls,
             max_reconnect_attempts,
             initial_reconnect_delay_ms,
             max_reconnect_delay_ms,
+            reconnect_jitter_pct,
+            reconnect_spread_secs,
+            circuit_open_threshold,
+            circuit_reset_secs,
+            message_buffer_size,
+            drain_timeout_secs,
             max_message_size,
+            connect_timeout_secs,
+            handshake_timeout_ms,
+            emit_eos_message,
+            emit_gap_markers,
+            enrich_metadata,
+            on_protocol_violation,
+            batch_size,
+            batch_timeout_ms,
+            request_reply_mode,
+            reply_timeout_ms,
+            max_messages_per_sec,
+            rate_limit_policy,
+            on_connect_send,
+            resubscribe_interval_secs,
+            idle_timeout_secs,
+            control_frames_as_activity,
+            ping_interval_secs,
+            pong_timeout_secs,
+            split_delimiter,
+            max_segments_per_frame,
+            pipeline,
+            message_filter,
+            message_filter_drop_non_json,
+            probe_before_link,
+            subject,
+            text_subject,
+            binary_subject,
+            subject_template,
+            heartbeat_subject,
+            heartbeat_interval_secs,
+            status_subject,
+            dead_letter_subject,
+            compression,
+            tls_min_version,
+            tls_max_version,
+            ca_cert_path,
+            client_cert_path,
+            client_key_path,
+            headers,
+            subprotocols,
+            auth_token,
+            proxy_url,
+            auto_upgrade_tls,
+            require_tls,
         })
     }
 
@@ -86,8 +1023,467 @@ impl LinkConfig {
         Duration::from_millis(self.initial_reconnect_delay_ms)
     }
 
+    /// `websocket_url` followed by the fallback `websocket_urls`
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.websocket_url.as_str())
+            .chain(self.websocket_urls.iter().map(String::as_str))
+    }
+
+    /// Failed attempts against one URL before failing over to the next
+    ///
+    /// `max_reconnect_attempts` is shared evenly between the URLs, with at
+    /// least one attempt each; with unlimited attempts every failure fails over.
+    pub fn attempts_per_url(&self) -> u32 {
+        let urls = self.websocket_urls.len() as u32 + 1;
+        (self.max_reconnect_attempts / urls).max(1)
+    }
+
+    /// Get the default reply timeout as Duration
+    pub fn reply_timeout(&self) -> Duration {
+        Duration::from_millis(self.reply_timeout_ms)
+    }
+
+    /// Get the batch timeout as Duration
+    pub fn batch_timeout(&self) -> Duration {
+        Duration::from_millis(self.batch_timeout_ms)
+    }
+
     /// Get the maximum reconnection delay as Duration
     pub fn max_reconnect_delay(&self) -> Duration {
         Duration::from_millis(self.max_reconnect_delay_ms)
     }
+
+    /// Get the reconnect spread as Duration
+    pub fn reconnect_spread(&self) -> Duration {
+        Duration::from_secs(self.reconnect_spread_secs)
+    }
+
+    /// Get the drain timeout as Duration
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs)
+    }
+
+    /// Get the circuit breaker suspension as Duration
+    pub fn circuit_reset(&self) -> Duration {
+        Duration::from_secs(self.circuit_reset_secs)
+    }
+
+    /// Proxy for connections to `target`, if any
+    ///
+    /// `proxy_url` takes precedence over the proxy environment variables.
+    pub fn proxy(&self, target: &Url) -> Option<Url> {
+        match &self.proxy_url {
+            Some(url) => Url::parse(url).ok(),
+            None => crate::proxy::from_env(target),
+        }
+    }
+
+    /// Get the TCP connect timeout as Duration
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    /// Get the WebSocket handshake timeout as Duration
+    pub fn handshake_timeout(&self) -> Duration {
+        Duration::from_millis(self.handshake_timeout_ms)
+    }
+
+    /// Whether a TLS version falls within the configured min/max bounds
+    pub fn allows_tls_version(&self, version: TlsVersion) -> bool {
+        self.tls_min_version.is_none_or(|min| version >= min)
+            && self.tls_max_version.is_none_or(|max| version <= max)
+    }
+
+    /// Host of the WebSocket URL, used to group connections per upstream
+    pub fn host(&self) -> Option<String> {
+        Url::parse(&self.websocket_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+    }
+
+    /// Get the idle timeout as Duration, if enabled
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_secs > 0).then(|| Duration::from_secs(self.idle_timeout_secs))
+    }
+
+    /// Get the ping interval and pong timeout as Durations, if pinging is enabled
+    pub fn ping(&self) -> Option<(Duration, Duration)> {
+        (self.ping_interval_secs > 0).then(|| {
+            (
+                Duration::from_secs(self.ping_interval_secs),
+                Duration::from_secs(self.pong_timeout_secs),
+            )
+        })
+    }
+
+    /// Subject for data messages of the given type
+    ///
+    /// `text_subject`/`binary_subject` take precedence over `subject`, which
+    /// replaces the default `websocket.<url>`.
+    pub fn data_subject(&self, message_type: MessageType) -> String {
+        let subject = match message_type {
+            MessageType::Text => &self.text_subject,
+            MessageType::Binary => &self.binary_subject,
+        };
+        subject
+            .as_ref()
+            .or(self.subject.as_ref())
+            .cloned()
+            .unwrap_or_else(|| format!("websocket.{}", self.websocket_url))
+    }
+
+    /// Subject for a data message, rendering `subject_template` when set
+    ///
+    /// Falls back to [`Self::data_subject`] when a placeholder has no value
+    /// in this message (a missing JSON field, or a payload that is not JSON)
+    /// or the rendered subject is not a valid subject.
+    pub fn message_subject(&self, message_type: MessageType, payload: &[u8]) -> String {
+        let Some(template) = &self.subject_template else {
+            return self.data_subject(message_type);
+        };
+        let mut json = None;
+        let rendered = substitute(template, |name| {
+            if name == "message_type" {
+                return Ok(message_type.to_string());
+            }
+            let path = name.strip_prefix("json.").unwrap_or(name);
+            let payload = json.get_or_insert_with(|| {
+                serde_json::from_slice::<serde_json::Value>(payload).unwrap_or_default()
+            });
+            json_field(payload, path).ok_or_else(|| anyhow::anyhow!("no value for `{{{}}}`", name))
+        })
+        .and_then(|subject| validate_subject(&subject).map(|_| subject));
+        match rendered {
+            Ok(subject) => subject,
+            Err(e) => {
+                tracing::debug!("subject_template not applied: {}", e);
+                self.data_subject(message_type)
+            }
+        }
+    }
+
+    /// Get the heartbeat subject and interval, if enabled
+    pub fn heartbeat(&self) -> Option<(&str, Duration)> {
+        match (&self.heartbeat_subject, self.heartbeat_interval_secs) {
+            (Some(subject), secs) if secs > 0 => Some((subject, Duration::from_secs(secs))),
+            _ => None,
+        }
+    }
+
+    /// Get the resubscribe interval as Duration, if enabled
+    pub fn resubscribe_interval(&self) -> Option<Duration> {
+        (self.resubscribe_interval_secs > 0)
+            .then(|| Duration::from_secs(self.resubscribe_interval_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn link(pairs: &[(&str, &str)]) -> anyhow::Result<LinkConfig> {
+        let mut config = values(pairs);
+        config
+            .entry("websocket_url".to_string())
+            .or_insert_with(|| "wss://feed.example.com/prices/btc".to_string());
+        LinkConfig::from_values(&config)
+    }
+
+    #[test]
+    fn from_values_applies_defaults() {
+        let config = link(&[]).unwrap();
+        assert_eq!(config.max_reconnect_attempts, 0);
+        assert_eq!(config.initial_reconnect_delay(), Duration::from_secs(1));
+        assert_eq!(config.max_reconnect_delay(), Duration::from_secs(60));
+        assert_eq!(config.reconnect_jitter_pct, 20);
+        assert_eq!(config.message_buffer_size, 1024);
+        assert_eq!(config.max_message_size, 1024 * 1024);
+        assert_eq!(config.connect_timeout(), Duration::from_secs(10));
+        assert_eq!(config.batch_size, 0);
+        assert_eq!(config.rate_limit_policy, RateLimitPolicy::Drop);
+        assert_eq!(
+            config.on_protocol_violation,
+            ProtocolViolationPolicy::Reconnect
+        );
+        assert!(config.websocket_urls.is_empty());
+        assert!(config.idle_timeout().is_none());
+        assert!(config.ping().is_none());
+        assert!(config.heartbeat().is_none());
+    }
+
+    #[test]
+    fn from_values_requires_a_websocket_url() {
+        let err = LinkConfig::from_values(&HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("websocket_url"));
+    }
+
+    #[test]
+    fn from_values_rejects_non_websocket_schemes() {
+        assert!(link(&[("websocket_url", "https://feed.example.com")]).is_err());
+        assert!(link(&[("websocket_url", "not a url")]).is_err());
+    }
+
+    #[test]
+    fn from_values_names_the_key_of_a_malformed_value() {
+        let err = link(&[("max_reconnect_attempts", "lots")]).unwrap_err();
+        assert!(err.to_string().contains("max_reconnect_attempts"));
+    }
+
+    #[test]
+    fn from_values_rejects_inconsistent_values() {
+        assert!(link(&[
+            ("initial_reconnect_delay_ms", "5000"),
+            ("max_reconnect_delay_ms", "1000")
+        ])
+        .is_err());
+        assert!(link(&[("reconnect_jitter_pct", "101")]).is_err());
+        assert!(link(&[("message_buffer_size", "0")]).is_err());
+        assert!(link(&[("batch_size", "10"), ("batch_timeout_ms", "0")]).is_err());
+        assert!(link(&[("request_reply_mode", "true"), ("reply_timeout_ms", "0")]).is_err());
+        assert!(link(&[("tls_min_version", "1.3"), ("tls_max_version", "1.2")]).is_err());
+        assert!(link(&[("client_cert_path", "/certs/client.pem")]).is_err());
+        assert!(link(&[("rate_limit_policy", "queue")]).is_err());
+    }
+
+    #[test]
+    fn from_values_enforces_require_tls() {
+        let plaintext = [
+            ("websocket_url", "ws://feed.example.com"),
+            ("require_tls", "true"),
+        ];
+        assert!(link(&plaintext).is_err());
+
+        let err = link(&[
+            ("websocket_urls", "wss://b.example.com,ws://c.example.com"),
+            ("require_tls", "true"),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("ws://c.example.com"));
+
+        let upgraded = link(&[
+            ("websocket_url", "ws://feed.example.com"),
+            ("require_tls", "true"),
+            ("auto_upgrade_tls", "true"),
+        ])
+        .unwrap();
+        assert!(upgraded.auto_upgrade_tls);
+    }
+
+    #[test]
+    fn from_values_parses_fallback_urls() {
+        let config = link(&[
+            ("max_reconnect_attempts", "9"),
+            (
+                "websocket_urls",
+                "wss://b.example.com, ,wss://c.example.com",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.urls().collect::<Vec<_>>(),
+            [
+                "wss://feed.example.com/prices/btc",
+                "wss://b.example.com",
+                "wss://c.example.com"
+            ]
+        );
+        assert_eq!(config.attempts_per_url(), 3);
+
+        let err = link(&[("websocket_urls", "https://b.example.com")]).unwrap_err();
+        assert!(err.to_string().contains("websocket_urls"));
+    }
+
+    #[test]
+    fn from_values_expands_url_placeholders_in_subjects() {
+        let config = link(&[
+            ("subject", "feeds.{host}.{path}"),
+            ("heartbeat_subject", "{scheme}.{port}.heartbeat"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.subject.as_deref(),
+            Some("feeds.feed.example.com.prices.btc")
+        );
+        assert_eq!(
+            config.heartbeat_subject.as_deref(),
+            Some("wss.443.heartbeat")
+        );
+
+        let err = link(&[("subject", "feeds.{tenant}")]).unwrap_err();
+        assert!(err.to_string().contains("subject"));
+        assert!(link(&[("text_subject", "feeds.*")]).is_err());
+    }
+
+    #[test]
+    fn data_subject_prefers_per_type_subjects() {
+        let config = link(&[("subject", "feeds"), ("binary_subject", "feeds.raw")]).unwrap();
+        assert_eq!(config.data_subject(MessageType::Text), "feeds");
+        assert_eq!(config.data_subject(MessageType::Binary), "feeds.raw");
+
+        let config = link(&[]).unwrap();
+        assert_eq!(
+            config.data_subject(MessageType::Text),
+            "websocket.wss://feed.example.com/prices/btc"
+        );
+    }
+
+    #[test]
+    fn message_subject_renders_the_template_per_message() {
+        let config = link(&[
+            ("subject", "feeds.other"),
+            (
+                "subject_template",
+                "feeds.{path}.{message_type}.{json.trade.symbol}",
+            ),
+        ])
+        .unwrap();
+
+        let payload = br#"{"trade": {"symbol": "BTC"}}"#;
+        assert_eq!(
+            config.message_subject(MessageType::Text, payload),
+            "feeds.prices.btc.text.BTC"
+        );
+
+        // Missing fields, non-JSON payloads and invalid tokens fall back
+        assert_eq!(
+            config.message_subject(MessageType::Text, br#"{"trade": {}}"#),
+            "feeds.other"
+        );
+        assert_eq!(
+            config.message_subject(MessageType::Binary, &[0xff, 0x00]),
+            "feeds.other"
+        );
+        assert_eq!(
+            config.message_subject(MessageType::Text, br#"{"trade": {"symbol": "a b"}}"#),
+            "feeds.other"
+        );
+    }
+
+    #[test]
+    fn from_values_rejects_unknown_template_placeholders() {
+        assert!(link(&[("subject_template", "feeds.{json.}")]).is_err());
+        assert!(link(&[("subject_template", "feeds.{tenant}")]).is_err());
+        assert!(link(&[("subject_template", "feeds.{json.id")]).is_err());
+    }
+
+    #[test]
+    fn validate_subject_follows_nats_token_rules() {
+        // Forwarded messages are published, so wildcards are never valid here
+        for (subject, valid) in [
+            ("feeds", true),
+            ("a.b.c", true),
+            ("tenant123.feeds.btc-usd", true),
+            ("", false),
+            ("feeds btc", false),
+            ("feeds\tbtc", false),
+            ("a..b", false),
+            (".feeds", false),
+            ("feeds.", false),
+            ("a.*", false),
+            ("a.>", false),
+            (">.a", false),
+        ] {
+            assert_eq!(
+                validate_subject(subject).is_ok(),
+                valid,
+                "{subject:?} should be {}",
+                if valid { "accepted" } else { "rejected" }
+            );
+        }
+    }
+
+    #[test]
+    fn provider_config_parses_yaml_with_anchors_and_merge_keys() {
+        let yaml = r#"
+defaults: &defaults
+  max_connections_per_host: 4
+  host_limit_policy: queue
+<<: *defaults
+subject_prefix: tenant123
+"#;
+        let config = ProviderConfig::from_yaml_str(yaml).unwrap();
+        assert_eq!(config.max_connections_per_host().unwrap(), Some(4));
+        assert_eq!(config.host_limit_policy().unwrap(), HostLimitPolicy::Queue);
+        assert_eq!(
+            config.subject_prefix().unwrap().as_deref(),
+            Some("tenant123")
+        );
+    }
+
+    #[test]
+    fn provider_config_formats_agree() {
+        let yaml =
+            ProviderConfig::from_yaml_str("admin_port: 8080\nsubject_prefix: a.b\n").unwrap();
+        let toml =
+            ProviderConfig::from_toml_str("admin_port = 8080\nsubject_prefix = \"a.b\"\n").unwrap();
+        let json =
+            ProviderConfig::from_json_str(r#"{"admin_port": 8080, "subject_prefix": "a.b"}"#)
+                .unwrap();
+        assert_eq!(yaml, toml);
+        assert_eq!(toml, json);
+    }
+
+    #[test]
+    fn provider_config_values_override_the_file() {
+        let path = std::env::temp_dir().join(format!(
+            "websocket-provider-config-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, "admin_port = 8080\nsubject_prefix = \"file\"\n").unwrap();
+        let config =
+            ProviderConfig::from_file_and_values(&path, &values(&[("subject_prefix", "init")]));
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.admin_port().unwrap(), Some(8080));
+        assert_eq!(config.subject_prefix().unwrap().as_deref(), Some("init"));
+    }
+
+    #[test]
+    fn provider_config_rejects_unsupported_file_formats() {
+        let path = std::env::temp_dir().join(format!(
+            "websocket-provider-config-{}.ini",
+            std::process::id()
+        ));
+        std::fs::write(&path, "admin_port = 8080\n").unwrap();
+        let err = ProviderConfig::from_file_and_values(&path, &HashMap::new());
+        std::fs::remove_file(&path).unwrap();
+
+        let err = err.unwrap_err().to_string();
+        assert!(err.contains("Unsupported config format: .ini"), "{err}");
+    }
+
+    #[test]
+    fn provider_config_builder_validates_values() {
+        let config = ProviderConfig::builder()
+            .set("max_connections_per_host", 2)
+            .set("subject_prefix", "tenant123.")
+            .build()
+            .unwrap();
+        assert_eq!(config.max_connections_per_host().unwrap(), Some(2));
+        assert_eq!(
+            config.subject_prefix().unwrap().as_deref(),
+            Some("tenant123")
+        );
+
+        assert!(ProviderConfig::builder()
+            .set("admin_port", 70000)
+            .build()
+            .is_err());
+        assert!(ProviderConfig::builder()
+            .set("host_limit_policy", "evict")
+            .build()
+            .is_err());
+        assert!(ProviderConfig::builder()
+            .set("subject_prefix", "tenant 123")
+            .build()
+            .is_err());
+    }
 }
@@ -0,0 +1,74 @@
+//! Error types for the WebSocket provider
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+/// Errors raised by the WebSocket client that callers may want to match on
+#[derive(Debug, Error)]
+pub enum ProviderError {
+    /// An operation did not complete within its configured timeout
+    #[error("{operation} timed out after {timeout:?}")]
+    Timeout {
+        operation: String,
+        timeout: Duration,
+    },
+
+    /// The server sent data that breaks the WebSocket protocol
+    #[error("WebSocket protocol violation: {0}")]
+    ProtocolViolation(String),
+
+    /// A received message is larger than `max_message_size`
+    #[error("message of {size} bytes exceeds the {limit} byte limit")]
+    MessageTooLarge { size: usize, limit: usize },
+
+    /// The server did not select one of the requested subprotocols
+    #[error("subprotocol negotiation failed (requested {requested:?}): {reason}")]
+    SubprotocolMismatch {
+        requested: Vec<String>,
+        reason: String,
+    },
+
+    /// The server refused the handshake's credentials with HTTP 401 or 403
+    #[error("server rejected the credentials with HTTP {status}")]
+    Unauthorized { status: u16 },
+
+    /// The configured proxy would not open a tunnel to the server
+    #[error("proxy {proxy} refused the connection: {reason}")]
+    ProxyRefused { proxy: String, reason: String },
+
+    /// A config value is invalid
+    #[error("invalid config: {0}")]
+    ConfigError(String),
+
+    /// Too many outbound frames are waiting for the connection
+    #[error("outbound queue is full")]
+    OutboundQueueFull,
+
+    /// The client has stopped and will not send any more frames
+    #[error("connection is closed")]
+    ConnectionClosed,
+
+    /// A text frame to send is not valid UTF-8
+    #[error("text frame is not valid UTF-8: {0}")]
+    InvalidText(#[from] std::string::FromUtf8Error),
+
+    /// A serialized message could not be parsed
+    #[error("invalid message: {0}")]
+    InvalidMessage(#[from] serde_json::Error),
+
+    /// A serialized message's `schema_version` is not a number
+    #[error("schema_version must be a non-negative integer")]
+    InvalidSchemaVersion,
+
+    /// A serialized message comes from a newer, unknown schema
+    #[error("unsupported message schema version {0}")]
+    UnsupportedSchemaVersion(u64),
+
+    /// A custom field uses the name of a built-in message field
+    #[error("custom field `{0}` shadows a reserved WebSocketMessage field")]
+    ReservedField(String),
+}
+
+/// Result type for operations that fail with a [`ProviderError`]
+pub type ProviderResult<T> = Result<T, ProviderError>;
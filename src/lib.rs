@@ -0,0 +1,33 @@
+//! WebSocket capability provider for wasmCloud
+//!
+//! This provider connects to remote WebSocket servers and forwards received messages
+//! to wasmCloud components via wRPC, with automatic reconnection and message size
+//! limits. Components can send frames back through the exported outbound interface.
+//!
+//! The client can also be used on its own: [`receive_messages`] connects once
+//! and collects messages into a `Vec`, which is handy in scripts and tests.
+
+#[cfg(feature = "rest-admin")]
+mod admin;
+mod circuit_breaker;
+mod compression;
+pub mod config;
+pub mod error;
+mod logging;
+pub mod message;
+mod metrics;
+pub mod pipeline;
+pub mod policy;
+mod probe;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod provider;
+mod proxy;
+mod rate_limit;
+mod timeline;
+mod transform;
+pub mod websocket;
+
+pub use metrics::MetricsSnapshot;
+pub use provider::WebSocketProvider;
+pub use websocket::receive_messages;
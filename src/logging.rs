@@ -0,0 +1,99 @@
+//! Log filter selection for the provider binary
+//!
+//! The SDK's tracing subscriber reads its filter from `RUST_LOG`, so the
+//! provider resolves its own sources into that variable before observability
+//! is initialized. In order of precedence:
+//!
+//! 1. `--log-filter <filter>` on the command line
+//! 2. `WEBSOCKET_PROVIDER_LOG`
+//! 3. `RUST_LOG`
+//! 4. the host's log level (`info` unless configured otherwise)
+//!
+//! Filters use the `RUST_LOG` directive syntax, e.g.
+//! `wasmcloud_provider_websocket::websocket=debug,info`.
+
+/// Provider-specific environment variable for the log filter
+pub const LOG_FILTER_ENV: &str = "WEBSOCKET_PROVIDER_LOG";
+
+/// Pick an override for `RUST_LOG` from the CLI argument or provider variable
+///
+/// Returns `None` when neither is set, leaving `RUST_LOG` (or the host's log
+/// level, which defaults to `info`) in charge.
+pub fn log_filter_override(
+    cli_filter: Option<String>,
+    provider_env: Option<String>,
+) -> Option<String> {
+    [cli_filter, provider_env]
+        .into_iter()
+        .flatten()
+        .find(|filter| !filter.trim().is_empty())
+}
+
+/// Extract `--log-filter <filter>` or `--log-filter=<filter>` from the arguments
+pub fn log_filter_arg(args: impl IntoIterator<Item = String>) -> Option<String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--log-filter" {
+            return args.next();
+        }
+        if let Some(filter) = arg.strip_prefix("--log-filter=") {
+            return Some(filter.to_string());
+        }
+    }
+    None
+}
+
+/// Export the configured log filter override, if any, as `RUST_LOG`
+///
+/// Must run before the provider's observability is initialized.
+pub fn apply_log_filter() {
+    if let Some(filter) = log_filter_override(
+        log_filter_arg(std::env::args().skip(1)),
+        std::env::var(LOG_FILTER_ENV).ok(),
+    ) {
+        std::env::set_var("RUST_LOG", filter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn log_filter_arg_reads_both_forms() {
+        assert_eq!(
+            log_filter_arg(args(&["--log-filter", "debug"])).as_deref(),
+            Some("debug")
+        );
+        assert_eq!(
+            log_filter_arg(args(&["--verbose", "--log-filter=websocket=trace,info"])).as_deref(),
+            Some("websocket=trace,info")
+        );
+    }
+
+    #[test]
+    fn log_filter_arg_is_none_without_a_value() {
+        assert_eq!(log_filter_arg(args(&[])), None);
+        assert_eq!(log_filter_arg(args(&["--log-filter"])), None);
+        assert_eq!(log_filter_arg(args(&["--log-filters=debug"])), None);
+    }
+
+    #[test]
+    fn log_filter_override_prefers_the_cli_and_skips_blanks() {
+        let cli = Some("debug".to_string());
+        let env = Some("warn".to_string());
+        assert_eq!(
+            log_filter_override(cli, env.clone()).as_deref(),
+            Some("debug")
+        );
+        assert_eq!(
+            log_filter_override(Some(" ".to_string()), env).as_deref(),
+            Some("warn")
+        );
+        assert_eq!(log_filter_override(None, Some(String::new())), None);
+    }
+}
@@ -1,14 +1,6 @@
-//! WebSocket capability provider for wasmCloud
-//!
-//! This provider connects to remote WebSocket servers and forwards received messages
-//! to wasmCloud components via wRPC. It implements unidirectional communication
-//! (receiving only) with automatic reconnection and message size limits.
+//! WebSocket capability provider binary
 
-mod config;
-mod provider;
-mod websocket;
-
-use provider::WebSocketProvider;
+use wasmcloud_provider_websocket::WebSocketProvider;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
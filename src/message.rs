@@ -0,0 +1,327 @@
+//! Messages handed from the WebSocket client to the forwarding path
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ProviderError, ProviderResult};
+
+/// Current serialized schema version of [`WebSocketMessage`]; see `SCHEMA.md`
+pub const SCHEMA_VERSION: u8 = 1;
+
+/// Field names that custom fields may not shadow when the message is serialized
+pub const RESERVED_FIELDS: &[&str] = &[
+    "schema_version",
+    "payload",
+    "message_type",
+    "subject",
+    "connection",
+    "payload_encoding",
+];
+
+/// Type of a received WebSocket message, taken from the frame opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageType {
+    Text,
+    Binary,
+}
+
+impl fmt::Display for MessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageType::Text => f.write_str("text"),
+            MessageType::Binary => f.write_str("binary"),
+        }
+    }
+}
+
+/// Details of the connection a message arrived on, attached with `enrich_metadata`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionMetadata {
+    /// Subprotocol negotiated during the handshake, if any
+    pub subprotocol: Option<String>,
+
+    /// IP address of the server the TCP connection went to
+    pub server_ip: Option<String>,
+
+    /// Number of the connection, starting at 1 and incremented on every reconnect
+    pub epoch: u64,
+
+    /// Milliseconds since this connection was established
+    pub uptime_ms: u64,
+
+    /// URL of the WebSocket server the message came from
+    #[serde(default)]
+    pub source_url: String,
+
+    /// Position of the message on this connection, starting at 1 for each epoch
+    #[serde(default)]
+    pub sequence: u64,
+}
+
+/// Schema version 0: the bare payload bytes, before frame types were tracked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LegacyWebSocketMessage {
+    /// Raw message bytes
+    pub payload: Vec<u8>,
+}
+
+/// A message to forward to the linked component
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebSocketMessage {
+    /// Serialized schema version, [`SCHEMA_VERSION`] for messages built by this crate
+    pub schema_version: u8,
+
+    /// Raw message bytes
+    pub payload: Vec<u8>,
+
+    /// Whether the frame was text or binary
+    pub message_type: MessageType,
+
+    /// Subject for provider-generated control messages (heartbeats, status);
+    /// data messages use the link's subject
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+
+    /// Connection details, set on data messages when `enrich_metadata` is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection: Option<ConnectionMetadata>,
+
+    /// Caller-supplied metadata (routing hints, processing flags), serialized
+    /// alongside the built-in fields
+    #[serde(flatten)]
+    pub custom_fields: HashMap<String, serde_json::Value>,
+}
+
+impl WebSocketMessage {
+    /// A text message received from the server
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            payload: text.into().into_bytes(),
+            message_type: MessageType::Text,
+            subject: None,
+            connection: None,
+            custom_fields: HashMap::new(),
+        }
+    }
+
+    /// A binary message received from the server
+    pub fn binary(data: Vec<u8>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            payload: data,
+            message_type: MessageType::Binary,
+            subject: None,
+            connection: None,
+            custom_fields: HashMap::new(),
+        }
+    }
+
+    /// Upgrade a schema version 0 message
+    ///
+    /// Version 0 did not record the frame type, so payloads that are valid
+    /// UTF-8 become text messages and anything else binary.
+    pub fn migrate_from_v0(legacy: LegacyWebSocketMessage) -> Self {
+        match String::from_utf8(legacy.payload) {
+            Ok(text) => Self::text(text),
+            Err(e) => Self::binary(e.into_bytes()),
+        }
+    }
+
+    /// Parse a serialized message of any supported schema version
+    ///
+    /// A missing `schema_version` field means version 0.
+    pub fn from_json_versioned(data: &[u8]) -> ProviderResult<Self> {
+        let value: serde_json::Value = serde_json::from_slice(data)?;
+        let version = value
+            .get("schema_version")
+            .map(|v| v.as_u64().ok_or(ProviderError::InvalidSchemaVersion))
+            .transpose()?
+            .unwrap_or(0);
+        match version {
+            0 => Ok(Self::migrate_from_v0(serde_json::from_value(value)?)),
+            1 => Ok(serde_json::from_value(value)?),
+            other => Err(ProviderError::UnsupportedSchemaVersion(other)),
+        }
+    }
+
+    /// Move `connection` into the payload, if the payload is a JSON object
+    ///
+    /// Non-JSON payloads are left unchanged and the metadata is dropped. Their
+    /// sequence number is still used, so consumers see it as a gap.
+    pub fn embed_connection(&mut self) {
+        let Some(connection) = self.connection.take() else {
+            return;
+        };
+        if let Ok(serde_json::Value::Object(mut object)) = serde_json::from_slice(&self.payload) {
+            object.insert(
+                "connection".to_string(),
+                serde_json::to_value(connection).unwrap_or_default(),
+            );
+            if let Ok(payload) = serde_json::to_vec(&object) {
+                self.payload = payload;
+            }
+        }
+    }
+
+    /// Serialize for a batch, with the payload as a string
+    ///
+    /// UTF-8 payloads are kept as text. Anything else is base64-encoded and the
+    /// entry gets `"payload_encoding": "base64"`.
+    pub fn to_batch_entry(&self) -> serde_json::Result<serde_json::Value> {
+        use base64::{engine::general_purpose, Engine as _};
+
+        let mut entry = serde_json::to_value(self)?;
+        let payload = match std::str::from_utf8(&self.payload) {
+            Ok(text) => text.to_string(),
+            Err(_) => {
+                entry["payload_encoding"] = "base64".into();
+                general_purpose::STANDARD.encode(&self.payload)
+            }
+        };
+        entry["payload"] = payload.into();
+        Ok(entry)
+    }
+
+    /// A JSON control message published to its own subject
+    pub fn control(subject: impl Into<String>, json: impl Into<String>) -> Self {
+        Self {
+            subject: Some(subject.into()),
+            ..Self::text(json)
+        }
+    }
+
+    /// Attach a custom metadata field
+    ///
+    /// Fails if `key` is one of [`RESERVED_FIELDS`], since it would collide
+    /// with a built-in field when serialized.
+    pub fn with_custom_field(
+        mut self,
+        key: impl Into<String>,
+        value: serde_json::Value,
+    ) -> ProviderResult<Self> {
+        let key = key.into();
+        if RESERVED_FIELDS.contains(&key.as_str()) {
+            return Err(ProviderError::ReservedField(key));
+        }
+        self.custom_fields.insert(key, value);
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_json_versioned_reads_the_current_schema() {
+        let message = WebSocketMessage::text("hello");
+        let json = serde_json::to_vec(&message).unwrap();
+        assert_eq!(
+            WebSocketMessage::from_json_versioned(&json).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn binary_payloads_round_trip_through_json() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let mut samples = vec![Vec::new(), (0..=255).collect::<Vec<u8>>()];
+        samples.extend((0..100).map(|_| {
+            let len = rng.gen_range(1..64);
+            (0..len).map(|_| rng.gen()).collect()
+        }));
+        for data in samples {
+            let message = WebSocketMessage::binary(data.clone());
+            let json = serde_json::to_vec(&message).unwrap();
+            let decoded = WebSocketMessage::from_json_versioned(&json).unwrap();
+            assert_eq!(decoded.payload, data);
+            assert_eq!(decoded.message_type, MessageType::Binary);
+        }
+    }
+
+    #[test]
+    fn from_json_versioned_migrates_version_0() {
+        let text = WebSocketMessage::from_json_versioned(br#"{"payload": [104, 105]}"#).unwrap();
+        assert_eq!(text, WebSocketMessage::text("hi"));
+        assert_eq!(text.schema_version, SCHEMA_VERSION);
+
+        let binary =
+            WebSocketMessage::from_json_versioned(br#"{"schema_version": 0, "payload": [255, 0]}"#)
+                .unwrap();
+        assert_eq!(binary, WebSocketMessage::binary(vec![255, 0]));
+    }
+
+    #[test]
+    fn from_json_versioned_rejects_bad_versions() {
+        assert!(matches!(
+            WebSocketMessage::from_json_versioned(br#"{"schema_version": 2, "payload": []}"#),
+            Err(ProviderError::UnsupportedSchemaVersion(2))
+        ));
+        assert!(matches!(
+            WebSocketMessage::from_json_versioned(br#"{"schema_version": "1", "payload": []}"#),
+            Err(ProviderError::InvalidSchemaVersion)
+        ));
+        assert!(matches!(
+            WebSocketMessage::from_json_versioned(b"not json"),
+            Err(ProviderError::InvalidMessage(_))
+        ));
+    }
+
+    #[test]
+    fn custom_fields_are_flattened_into_the_json() {
+        let message = WebSocketMessage::text("hi")
+            .with_custom_field("route", serde_json::json!("eu"))
+            .unwrap();
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["route"], "eu");
+        assert_eq!(
+            WebSocketMessage::from_json_versioned(json.to_string().as_bytes()).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn reserved_fields_are_the_serialized_fields() {
+        // Every optional field set, and a payload that needs base64 in a batch
+        let mut message = WebSocketMessage::binary(vec![0xff]);
+        message.subject = Some("status".to_string());
+        message.connection = Some(ConnectionMetadata {
+            subprotocol: None,
+            server_ip: None,
+            epoch: 1,
+            uptime_ms: 0,
+            source_url: "wss://feed.example.com".to_string(),
+            sequence: 1,
+        });
+
+        let mut serialized: Vec<String> = [
+            serde_json::to_value(&message).unwrap(),
+            message.to_batch_entry().unwrap(),
+        ]
+        .iter()
+        .flat_map(|value| value.as_object().unwrap().keys().cloned())
+        .collect();
+        serialized.sort();
+        serialized.dedup();
+
+        let mut reserved: Vec<String> = RESERVED_FIELDS.iter().map(|f| f.to_string()).collect();
+        reserved.sort();
+        assert_eq!(serialized, reserved);
+    }
+
+    #[test]
+    fn custom_fields_may_not_shadow_reserved_fields() {
+        for key in RESERVED_FIELDS {
+            let err = WebSocketMessage::text("hi")
+                .with_custom_field(*key, serde_json::Value::Null)
+                .unwrap_err();
+            assert!(matches!(err, ProviderError::ReservedField(field) if field == *key));
+        }
+    }
+}
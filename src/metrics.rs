@@ -0,0 +1,220 @@
+//! Per-connection message counters
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Window over which the message rate is averaged
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Sliding-window message rate
+#[derive(Debug)]
+pub struct RateCounter {
+    window: Duration,
+    instants: VecDeque<Instant>,
+}
+
+impl Default for RateCounter {
+    fn default() -> Self {
+        Self::new(RATE_WINDOW)
+    }
+}
+
+impl RateCounter {
+    /// Create a counter averaging over `window`
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            instants: VecDeque::new(),
+        }
+    }
+
+    /// Count one message now
+    pub fn record(&mut self) {
+        let now = Instant::now();
+        self.evict(now);
+        self.instants.push_back(now);
+    }
+
+    /// Messages per second averaged over the window
+    pub fn rate_per_second(&mut self) -> f64 {
+        self.evict(Instant::now());
+        self.instants.len() as f64 / self.window.as_secs_f64()
+    }
+
+    /// Drop instants that have fallen out of the window
+    fn evict(&mut self, now: Instant) {
+        while let Some(oldest) = self.instants.front() {
+            if now.duration_since(*oldest) <= self.window {
+                break;
+            }
+            self.instants.pop_front();
+        }
+    }
+}
+
+/// Point-in-time copy of a connection's counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Data messages received from the server
+    pub messages_received: u64,
+    /// Messages delivered to the component
+    pub messages_forwarded: u64,
+    /// Messages dropped before reaching the component
+    pub messages_dropped: u64,
+    /// Messages not forwarded because they did not match `message_filter`
+    pub messages_filtered: u64,
+    /// Messages dropped or delayed by `max_messages_per_sec`
+    pub messages_throttled: u64,
+    /// Reconnection attempts after a failed or lost connection
+    pub reconnect_count: u64,
+    /// Unix time in seconds of the last received message, if any
+    pub last_message_timestamp_secs: Option<u64>,
+}
+
+/// Message counters for one WebSocket client
+#[derive(Debug, Default)]
+pub struct ClientStats {
+    messages_received: u64,
+    messages_forwarded: u64,
+    messages_dropped: u64,
+    messages_filtered: u64,
+    messages_throttled: u64,
+    reconnect_count: u64,
+    last_message_at: Option<SystemTime>,
+    protocol_violations_total: u64,
+    oversized_messages_total: u64,
+    rate: RateCounter,
+}
+
+impl ClientStats {
+    /// Count one received data message
+    pub fn record_message(&mut self) {
+        self.messages_received += 1;
+        self.last_message_at = Some(SystemTime::now());
+        self.rate.record();
+    }
+
+    /// Count one message delivered to the component
+    pub fn record_forwarded(&mut self) {
+        self.messages_forwarded += 1;
+    }
+
+    /// Count one message dropped before reaching the component
+    pub fn record_dropped(&mut self) {
+        self.messages_dropped += 1;
+    }
+
+    /// Count one message rejected by `message_filter`
+    pub fn record_filtered(&mut self) {
+        self.messages_filtered += 1;
+    }
+
+    /// Count one message held back by the rate limit, and dropped unless `delayed`
+    pub fn record_throttled(&mut self, delayed: bool) {
+        self.messages_throttled += 1;
+        if !delayed {
+            self.messages_dropped += 1;
+        }
+    }
+
+    /// Count one reconnection attempt
+    pub fn record_reconnect(&mut self) {
+        self.reconnect_count += 1;
+    }
+
+    /// Count one protocol violation by the server
+    pub fn record_protocol_violation(&mut self) {
+        self.protocol_violations_total += 1;
+    }
+
+    /// Count one message dropped for exceeding `max_message_size`
+    pub fn record_oversized_message(&mut self) {
+        self.oversized_messages_total += 1;
+        self.messages_dropped += 1;
+    }
+
+    /// Total data messages received since the client started
+    pub fn messages_received(&self) -> u64 {
+        self.messages_received
+    }
+
+    /// Total protocol violations since the client started
+    pub fn protocol_violations_total(&self) -> u64 {
+        self.protocol_violations_total
+    }
+
+    /// Total messages dropped for exceeding `max_message_size`
+    pub fn oversized_messages_total(&self) -> u64 {
+        self.oversized_messages_total
+    }
+
+    /// Copy of the delivery counters
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            messages_received: self.messages_received,
+            messages_forwarded: self.messages_forwarded,
+            messages_dropped: self.messages_dropped,
+            messages_filtered: self.messages_filtered,
+            messages_throttled: self.messages_throttled,
+            reconnect_count: self.reconnect_count,
+            last_message_timestamp_secs: self.last_message_at.and_then(|at| {
+                at.duration_since(UNIX_EPOCH)
+                    .ok()
+                    .map(|since| since.as_secs())
+            }),
+        }
+    }
+
+    /// Current message rate, averaged over the last minute
+    pub fn current_rate_per_second(&mut self) -> f64 {
+        self.rate.rate_per_second()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_counter_averages_over_the_window() {
+        let mut counter = RateCounter::new(Duration::from_secs(2));
+        for _ in 0..10 {
+            counter.record();
+        }
+        assert_eq!(counter.rate_per_second(), 5.0);
+    }
+
+    #[test]
+    fn rate_counter_forgets_old_messages() {
+        let mut counter = RateCounter::new(Duration::from_millis(20));
+        counter.record();
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(counter.rate_per_second(), 0.0);
+        counter.record();
+        assert_eq!(counter.rate_per_second(), 50.0);
+    }
+
+    #[test]
+    fn snapshot_reflects_recorded_counters() {
+        let mut stats = ClientStats::default();
+        stats.record_message();
+        stats.record_message();
+        stats.record_forwarded();
+        stats.record_filtered();
+        stats.record_throttled(true);
+        stats.record_throttled(false);
+        stats.record_oversized_message();
+        stats.record_reconnect();
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.messages_received, 2);
+        assert_eq!(snapshot.messages_forwarded, 1);
+        assert_eq!(snapshot.messages_filtered, 1);
+        assert_eq!(snapshot.messages_throttled, 2);
+        // One undelayed throttle and one oversized message
+        assert_eq!(snapshot.messages_dropped, 2);
+        assert_eq!(snapshot.reconnect_count, 1);
+        assert!(snapshot.last_message_timestamp_secs.is_some());
+        assert_eq!(stats.oversized_messages_total(), 1);
+    }
+}
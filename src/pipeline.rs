@@ -0,0 +1,401 @@
+//! Ordered message transformation pipeline
+//!
+//! A link can configure `pipeline` as a JSON array of stages that run in order
+//! on every data message before it is forwarded. Each stage takes one message
+//! and yields zero or more, so filters can drop messages and `split` and
+//! `unpack_array` can fan a frame out into several.
+//!
+//! JSON stages (`filter`, `project`, `rename`, `add_field`) operate on payloads
+//! that parse as a JSON object. `filter` drops anything else; the other JSON
+//! stages pass non-JSON payloads through unchanged.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::config::CompressionAlgorithm;
+use crate::message::{MessageType, WebSocketMessage};
+
+/// A single pipeline stage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum Stage {
+    /// Keep only JSON messages whose `field` equals `equals`
+    Filter { field: String, equals: Value },
+    /// Keep only the listed top-level fields
+    Project { fields: Vec<String> },
+    /// Rename a top-level field
+    Rename { from: String, to: String },
+    /// Set a top-level field to a fixed value
+    AddField { field: String, value: Value },
+    /// Split a text payload on a delimiter into one message per non-empty segment
+    Split { delimiter: String },
+    /// Forward each element of a top-level JSON array as its own message
+    UnpackArray,
+    /// Cut payloads down to at most `max_bytes`
+    Truncate { max_bytes: usize },
+    /// Decompress a gzip or zstd payload (requires the `compression` feature)
+    Decompress { algorithm: CompressionAlgorithm },
+}
+
+/// A `message_filter` predicate such as `$.event_type == "trade"`
+///
+/// Compares the value at a dotted JSON path with a JSON literal using `==`
+/// or `!=`. A missing field compares unequal.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageFilter {
+    path: String,
+    negate: bool,
+    value: Value,
+}
+
+impl std::str::FromStr for MessageFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        // The first operator splits, so literals may contain `==` or `!=`
+        let operator = s
+            .find("==")
+            .into_iter()
+            .chain(s.find("!="))
+            .min()
+            .ok_or_else(|| {
+                anyhow::anyhow!("expected `$.<field> == <json>` or `$.<field> != <json>`")
+            })?;
+        let (path, literal) = (&s[..operator], &s[operator + 2..]);
+        let negate = s[operator..].starts_with("!=");
+        let path = path
+            .trim()
+            .strip_prefix("$.")
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("path must start with `$.`"))?;
+        let value = serde_json::from_str(literal.trim())
+            .map_err(|e| anyhow::anyhow!("invalid JSON literal: {}", e))?;
+        Ok(Self {
+            path: path.to_string(),
+            negate,
+            value,
+        })
+    }
+}
+
+impl MessageFilter {
+    /// Whether a message matches, or `None` if its payload is not a JSON object
+    pub fn matches(&self, message: &WebSocketMessage) -> Option<bool> {
+        let object = as_json_object(message)?;
+        let equal = lookup(&object, &self.path) == Some(&self.value);
+        Some(equal != self.negate)
+    }
+}
+
+/// Parse a pipeline from its JSON config representation
+///
+/// Without the `compression` feature a `decompress` stage is rejected here,
+/// rather than failing on every message once the link is up.
+pub fn parse(json: &str) -> anyhow::Result<Vec<Stage>> {
+    let stages: Vec<Stage> =
+        serde_json::from_str(json).map_err(|e| anyhow::anyhow!("Invalid pipeline: {}", e))?;
+    if !cfg!(feature = "compression")
+        && stages
+            .iter()
+            .any(|stage| matches!(stage, Stage::Decompress { .. }))
+    {
+        anyhow::bail!(
+            "Invalid pipeline: decompress requires the provider to be built with the `compression` feature"
+        );
+    }
+    Ok(stages)
+}
+
+/// Run every stage in order, returning the messages left to forward
+pub fn run(stages: &[Stage], message: WebSocketMessage) -> anyhow::Result<Vec<WebSocketMessage>> {
+    let mut messages = vec![message];
+    for stage in stages {
+        let mut next = Vec::with_capacity(messages.len());
+        for message in messages {
+            next.extend(stage.apply(message)?);
+        }
+        messages = next;
+    }
+    Ok(messages)
+}
+
+impl Stage {
+    /// Apply this stage to one message
+    fn apply(&self, message: WebSocketMessage) -> anyhow::Result<Vec<WebSocketMessage>> {
+        match self {
+            Stage::Filter { field, equals } => {
+                let matches = as_json_object(&message)
+                    .is_some_and(|object| lookup(&object, field) == Some(equals));
+                Ok(if matches { vec![message] } else { vec![] })
+            }
+            Stage::Project { fields } => map_json(message, |object| {
+                object.retain(|key, _| fields.contains(key));
+            }),
+            Stage::Rename { from, to } => map_json(message, |object| {
+                if let Some(value) = object.remove(from) {
+                    object.insert(to.clone(), value);
+                }
+            }),
+            Stage::AddField { field, value } => map_json(message, |object| {
+                object.insert(field.clone(), value.clone());
+            }),
+            Stage::Split { delimiter } => match message.message_type {
+                MessageType::Text => Ok(String::from_utf8_lossy(&message.payload)
+                    .split(delimiter.as_str())
+                    .filter(|segment| !segment.trim().is_empty())
+                    .map(WebSocketMessage::text)
+                    .collect()),
+                MessageType::Binary => Ok(vec![message]),
+            },
+            Stage::UnpackArray => match serde_json::from_slice(&message.payload) {
+                Ok(Value::Array(elements)) => elements
+                    .iter()
+                    .map(|element| Ok(WebSocketMessage::text(serde_json::to_string(element)?)))
+                    .collect(),
+                _ => Ok(vec![message]),
+            },
+            Stage::Truncate { max_bytes } => {
+                let mut payload = message.payload;
+                let mut len = payload.len().min(*max_bytes);
+                // Keep text valid UTF-8 by cutting on a character boundary
+                if message.message_type == MessageType::Text {
+                    while len > 0 && std::str::from_utf8(&payload[..len]).is_err() {
+                        len -= 1;
+                    }
+                }
+                payload.truncate(len);
+                Ok(vec![WebSocketMessage { payload, ..message }])
+            }
+            Stage::Decompress { algorithm } => {
+                let payload = crate::compression::decompress(&message.payload, *algorithm)?;
+                Ok(vec![WebSocketMessage { payload, ..message }])
+            }
+        }
+    }
+}
+
+/// Parse a payload as a JSON object, if it is one
+fn as_json_object(message: &WebSocketMessage) -> Option<Map<String, Value>> {
+    match serde_json::from_slice(&message.payload) {
+        Ok(Value::Object(object)) => Some(object),
+        _ => None,
+    }
+}
+
+/// Look up a dotted field path (e.g. `data.type`) in a JSON object
+fn lookup<'a>(object: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut parts = path.split('.');
+    let mut value = object.get(parts.next()?)?;
+    for part in parts {
+        value = value.get(part)?;
+    }
+    Some(value)
+}
+
+/// Edit a JSON object payload in place, passing non-JSON payloads through
+fn map_json(
+    message: WebSocketMessage,
+    edit: impl FnOnce(&mut Map<String, Value>),
+) -> anyhow::Result<Vec<WebSocketMessage>> {
+    let Some(mut object) = as_json_object(&message) else {
+        return Ok(vec![message]);
+    };
+    edit(&mut object);
+    Ok(vec![WebSocketMessage {
+        payload: serde_json::to_vec(&object)?,
+        ..message
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payloads(messages: &[WebSocketMessage]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| std::str::from_utf8(&message.payload).unwrap())
+            .collect()
+    }
+
+    fn json(message: &WebSocketMessage) -> Value {
+        serde_json::from_slice(&message.payload).unwrap()
+    }
+
+    #[test]
+    fn parse_reads_tagged_stages() {
+        let stages = parse(
+            r#"[{"stage": "filter", "field": "type", "equals": "trade"},
+                {"stage": "unpack_array"},
+                {"stage": "truncate", "max_bytes": 16}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            stages,
+            [
+                Stage::Filter {
+                    field: "type".to_string(),
+                    equals: Value::from("trade")
+                },
+                Stage::UnpackArray,
+                Stage::Truncate { max_bytes: 16 },
+            ]
+        );
+        assert!(parse(r#"[{"stage": "explode"}]"#).is_err());
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn decompress_needs_the_feature() {
+        let err = parse(r#"[{"stage": "decompress", "algorithm": "gzip"}]"#).unwrap_err();
+        assert!(err.to_string().contains("`compression` feature"));
+    }
+
+    #[test]
+    fn filter_keeps_matching_json_only() {
+        let stage = Stage::Filter {
+            field: "data.type".to_string(),
+            equals: Value::from("trade"),
+        };
+        let trade = WebSocketMessage::text(r#"{"data": {"type": "trade"}}"#);
+        let quote = WebSocketMessage::text(r#"{"data": {"type": "quote"}}"#);
+        assert_eq!(stage.apply(trade).unwrap().len(), 1);
+        assert!(stage.apply(quote).unwrap().is_empty());
+        assert!(stage
+            .apply(WebSocketMessage::text("not json"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn json_stages_edit_objects_and_pass_other_payloads_through() {
+        let stages = [
+            Stage::Project {
+                fields: vec!["p".to_string(), "s".to_string()],
+            },
+            Stage::Rename {
+                from: "p".to_string(),
+                to: "price".to_string(),
+            },
+            Stage::AddField {
+                field: "source".to_string(),
+                value: Value::from("feed"),
+            },
+        ];
+        let message = WebSocketMessage::text(r#"{"p": 1.5, "s": "BTC", "noise": true}"#);
+        let out = run(&stages, message).unwrap();
+        assert_eq!(
+            json(&out[0]),
+            serde_json::json!({"price": 1.5, "s": "BTC", "source": "feed"})
+        );
+
+        let out = run(&stages, WebSocketMessage::text("plain")).unwrap();
+        assert_eq!(payloads(&out), ["plain"]);
+    }
+
+    #[test]
+    fn split_fans_text_out_into_segments() {
+        let stage = Stage::Split {
+            delimiter: "\n".to_string(),
+        };
+        let out = stage
+            .apply(WebSocketMessage::text("a\n\n b \nc\n"))
+            .unwrap();
+        assert_eq!(payloads(&out), ["a", " b ", "c"]);
+
+        let binary = WebSocketMessage::binary(b"a\nb".to_vec());
+        assert_eq!(stage.apply(binary).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn unpack_array_forwards_each_element() {
+        let out = Stage::UnpackArray
+            .apply(WebSocketMessage::text(r#"[{"id": 1}, 2, "three"]"#))
+            .unwrap();
+        assert_eq!(payloads(&out), [r#"{"id":1}"#, "2", r#""three""#]);
+
+        let out = Stage::UnpackArray
+            .apply(WebSocketMessage::text(r#"{"id": 1}"#))
+            .unwrap();
+        assert_eq!(payloads(&out), [r#"{"id": 1}"#]);
+    }
+
+    #[test]
+    fn truncate_keeps_text_valid_utf8() {
+        let stage = Stage::Truncate { max_bytes: 4 };
+        // "né€" is 1 + 2 + 3 bytes; cutting at 4 would split the euro sign
+        let out = stage.apply(WebSocketMessage::text("né€")).unwrap();
+        assert_eq!(payloads(&out), ["né"]);
+
+        let out = stage
+            .apply(WebSocketMessage::binary(vec![0xe2, 0x82, 0xac, 0xe2, 0x82]))
+            .unwrap();
+        assert_eq!(out[0].payload, [0xe2, 0x82, 0xac, 0xe2]);
+
+        let out = stage.apply(WebSocketMessage::text("ab")).unwrap();
+        assert_eq!(payloads(&out), ["ab"]);
+    }
+
+    #[test]
+    fn run_chains_stages_in_order() {
+        let stages = [
+            Stage::UnpackArray,
+            Stage::Filter {
+                field: "keep".to_string(),
+                equals: Value::Bool(true),
+            },
+        ];
+        let message = WebSocketMessage::text(
+            r#"[{"keep": true, "id": 1}, {"keep": false}, {"keep": true, "id": 3}]"#,
+        );
+        let out = run(&stages, message).unwrap();
+        let ids: Vec<_> = out
+            .iter()
+            .map(|message| json(message)["id"].clone())
+            .collect();
+        assert_eq!(ids, [Value::from(1), Value::from(3)]);
+    }
+
+    #[test]
+    fn message_filter_parses_both_operators() {
+        let filter: MessageFilter = r#"$.data.type == "trade""#.parse().unwrap();
+        assert_eq!(filter.path, "data.type");
+        assert!(!filter.negate);
+        assert_eq!(filter.value, Value::from("trade"));
+
+        let filter: MessageFilter = "$.price!=0".parse().unwrap();
+        assert!(filter.negate);
+        assert_eq!(filter.value, Value::from(0));
+
+        // Only the first operator splits
+        let filter: MessageFilter = r#"$.expr == "a != b""#.parse().unwrap();
+        assert_eq!(filter.value, Value::from("a != b"));
+    }
+
+    #[test]
+    fn message_filter_rejects_malformed_predicates() {
+        for predicate in ["$.type", "type == 1", "$. == 1", "$.type == trade"] {
+            assert!(
+                predicate.parse::<MessageFilter>().is_err(),
+                "{predicate:?} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn message_filter_matches_json_objects() {
+        let filter: MessageFilter = r#"$.event.type == "trade""#.parse().unwrap();
+        let trade = WebSocketMessage::text(r#"{"event": {"type": "trade"}}"#);
+        let quote = WebSocketMessage::text(r#"{"event": {"type": "quote"}}"#);
+        let missing = WebSocketMessage::text(r#"{"event": {}}"#);
+        assert_eq!(filter.matches(&trade), Some(true));
+        assert_eq!(filter.matches(&quote), Some(false));
+        assert_eq!(filter.matches(&missing), Some(false));
+        assert_eq!(filter.matches(&WebSocketMessage::text("[1, 2]")), None);
+        assert_eq!(filter.matches(&WebSocketMessage::text("ping")), None);
+
+        let filter: MessageFilter = r#"$.event.type != "trade""#.parse().unwrap();
+        assert_eq!(filter.matches(&trade), Some(false));
+        assert_eq!(filter.matches(&missing), Some(true));
+    }
+}
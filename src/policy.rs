@@ -0,0 +1,185 @@
+//! Provider-level policy constraining what link configs components may supply
+//!
+//! The policy is set by the platform operator with the `link_policy` provider
+//! setting (or the `WEBSOCKET_PROVIDER_LINK_POLICY` environment variable) as a
+//! JSON object, e.g.
+//! `{"require_tls": true, "forbid_insecure_tls": true, "subject_prefix": "tenant123"}`.
+//! Links that break any rule are rejected when they are put.
+
+use serde::Deserialize;
+
+use crate::config::LinkConfig;
+
+/// Environment variable consulted when `link_policy` is not in the provider config
+pub const LINK_POLICY_ENV: &str = "WEBSOCKET_PROVIDER_LINK_POLICY";
+
+/// Rules every link config must satisfy
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkPolicy {
+    /// Only allow wss:// URLs
+    pub require_tls: bool,
+
+    /// Reject links whose TLS attempt may silently fall back to plaintext
+    pub forbid_insecure_tls: bool,
+
+    /// Subjects a link sets must live under this prefix
+    pub subject_prefix: Option<String>,
+}
+
+impl LinkPolicy {
+    /// Parse a policy from its JSON representation
+    pub fn from_json_str(s: &str) -> anyhow::Result<LinkPolicy> {
+        serde_json::from_str(s).map_err(|e| anyhow::anyhow!("Invalid link_policy: {}", e))
+    }
+
+    /// Check a link against the policy, naming the first rule it breaks
+    pub fn check(&self, link: &LinkConfig) -> anyhow::Result<()> {
+        if self.require_tls {
+            if let Some(url) = link.urls().find(|url| !url.starts_with("wss://")) {
+                anyhow::bail!(
+                    "Link rejected by policy: websocket URLs must use wss:// (got {})",
+                    url
+                );
+            }
+        }
+
+        if self.forbid_insecure_tls && link.auto_upgrade_tls && !link.require_tls {
+            anyhow::bail!(
+                "Link rejected by policy: auto_upgrade_tls may fall back to plaintext unless require_tls is set"
+            );
+        }
+
+        if let Some(prefix) = &self.subject_prefix {
+            let prefix = prefix.trim_end_matches('.');
+            let subjects = [
+                ("subject", &link.subject),
+                ("text_subject", &link.text_subject),
+                ("binary_subject", &link.binary_subject),
+                ("subject_template", &link.subject_template),
+                ("status_subject", &link.status_subject),
+                ("dead_letter_subject", &link.dead_letter_subject),
+                ("heartbeat_subject", &link.heartbeat_subject),
+            ];
+            for (key, subject) in subjects {
+                let Some(subject) = subject else { continue };
+                let under_prefix = subject
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('.'));
+                if !under_prefix {
+                    anyhow::bail!(
+                        "Link rejected by policy: {} {} must be under {}.",
+                        key,
+                        subject,
+                        prefix
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn link(pairs: &[(&str, &str)]) -> LinkConfig {
+        let mut values: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        values
+            .entry("websocket_url".to_string())
+            .or_insert_with(|| "wss://feed.example.com".to_string());
+        LinkConfig::from_values(&values).unwrap()
+    }
+
+    #[test]
+    fn default_policy_allows_everything() {
+        let link = link(&[
+            ("websocket_url", "ws://feed.example.com"),
+            ("auto_upgrade_tls", "true"),
+            ("subject", "anywhere"),
+        ]);
+        LinkPolicy::default().check(&link).unwrap();
+    }
+
+    #[test]
+    fn from_json_str_rejects_unknown_rules() {
+        let policy = LinkPolicy::from_json_str(r#"{"require_tls": true}"#).unwrap();
+        assert!(policy.require_tls);
+        assert!(LinkPolicy::from_json_str(r#"{"require_mtls": true}"#).is_err());
+    }
+
+    #[test]
+    fn require_tls_checks_every_url() {
+        let policy = LinkPolicy {
+            require_tls: true,
+            ..Default::default()
+        };
+        policy
+            .check(&link(&[("websocket_urls", "wss://b.example.com")]))
+            .unwrap();
+
+        let err = policy
+            .check(&link(&[(
+                "websocket_urls",
+                "wss://b.example.com,ws://c.example.com",
+            )]))
+            .unwrap_err();
+        assert!(err.to_string().contains("ws://c.example.com"));
+    }
+
+    #[test]
+    fn forbid_insecure_tls_rejects_plaintext_fallback() {
+        let policy = LinkPolicy {
+            forbid_insecure_tls: true,
+            ..Default::default()
+        };
+        let fallback = link(&[
+            ("websocket_url", "ws://feed.example.com"),
+            ("auto_upgrade_tls", "true"),
+        ]);
+        assert!(policy.check(&fallback).is_err());
+
+        let enforced = link(&[
+            ("websocket_url", "ws://feed.example.com"),
+            ("auto_upgrade_tls", "true"),
+            ("require_tls", "true"),
+        ]);
+        policy.check(&enforced).unwrap();
+    }
+
+    #[test]
+    fn subject_prefix_checks_every_subject() {
+        let policy = LinkPolicy {
+            subject_prefix: Some("tenant123.".to_string()),
+            ..Default::default()
+        };
+        policy
+            .check(&link(&[
+                ("subject", "tenant123.feeds"),
+                ("heartbeat_subject", "tenant123.heartbeat"),
+            ]))
+            .unwrap();
+
+        for key in [
+            "subject",
+            "text_subject",
+            "binary_subject",
+            "subject_template",
+            "status_subject",
+            "dead_letter_subject",
+            "heartbeat_subject",
+        ] {
+            let err = policy
+                .check(&link(&[(key, "tenant1234.feeds")]))
+                .unwrap_err();
+            assert!(err.to_string().contains(key), "{key} was not checked");
+        }
+    }
+}
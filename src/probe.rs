@@ -0,0 +1,358 @@
+//! Connectivity probe for validating a link's endpoints before committing to them
+//!
+//! A probe runs each connection phase separately (TCP connect, TLS handshake,
+//! WebSocket upgrade, close) and reports per-phase success and timing, so a
+//! misconfigured URL, certificate or server shows up as an actionable error.
+//! It connects exactly as the client would: every URL in `websocket_url` and
+//! `websocket_urls` is probed, `auto_upgrade_tls` and `require_tls` pick the
+//! URLs tried, and the link's connect and handshake timeouts apply.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::timeout_at;
+use tokio_rustls::TlsConnector;
+use url::Url;
+
+use crate::config::LinkConfig;
+use crate::websocket::{
+    connect_tcp, connection_urls, handshake_request, handshake_timeout_error, require_tls_error,
+    tls_client_config,
+};
+
+/// Outcome of a single probe phase
+#[derive(Debug, Clone)]
+pub struct ProbePhase {
+    /// Phase name: `parse_url`, `tcp_connect`, `tls_handshake`, `websocket_upgrade` or `close`
+    pub name: &'static str,
+    /// How long the phase took
+    pub duration: Duration,
+    /// Error message if the phase failed
+    pub error: Option<String>,
+}
+
+/// Phases of one connection attempt
+#[derive(Debug, Clone)]
+pub struct ProbeAttempt {
+    /// Configured URL the attempt belongs to
+    pub endpoint: String,
+    /// URL connected to, which differs from `endpoint` after a TLS upgrade
+    pub url: String,
+    pub phases: Vec<ProbePhase>,
+}
+
+impl ProbeAttempt {
+    fn new(endpoint: &str, url: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            url: url.to_string(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// Whether every phase that ran succeeded
+    pub fn is_success(&self) -> bool {
+        !self.phases.is_empty() && self.phases.iter().all(|phase| phase.error.is_none())
+    }
+
+    /// Record a phase outcome, returning the value on success
+    fn record<T, E: fmt::Display>(
+        &mut self,
+        name: &'static str,
+        started: Instant,
+        result: Result<T, E>,
+    ) -> Option<T> {
+        let (value, error) = match result {
+            Ok(value) => (Some(value), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.phases.push(ProbePhase {
+            name,
+            duration: started.elapsed(),
+            error,
+        });
+        value
+    }
+}
+
+/// Per-phase diagnostics of a connectivity probe
+#[derive(Debug, Clone, Default)]
+pub struct ProbeResult {
+    pub attempts: Vec<ProbeAttempt>,
+}
+
+impl ProbeResult {
+    /// Whether every probed endpoint had an attempt succeed
+    ///
+    /// A failed TLS upgrade followed by a working plaintext fallback counts
+    /// as a success, as it would for the client.
+    pub fn is_success(&self) -> bool {
+        !self.attempts.is_empty()
+            && self.attempts.iter().all(|attempt| {
+                self.attempts
+                    .iter()
+                    .any(|other| other.endpoint == attempt.endpoint && other.is_success())
+            })
+    }
+}
+
+impl fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, attempt) in self.attempts.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: ", attempt.url)?;
+            for (i, phase) in attempt.phases.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                match &phase.error {
+                    None => write!(f, "{} ok ({:?})", phase.name, phase.duration)?,
+                    Some(e) => write!(f, "{} failed ({:?}): {}", phase.name, phase.duration, e)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Probe every endpoint of the link: connect, handshake, upgrade, then close
+pub async fn probe_connection(config: &LinkConfig) -> ProbeResult {
+    let mut result = ProbeResult::default();
+    for endpoint in config.urls() {
+        probe_endpoint(&mut result, config, endpoint).await;
+    }
+    result
+}
+
+/// Probe the URLs the client would try for one endpoint, stopping at the first that works
+async fn probe_endpoint(result: &mut ProbeResult, config: &LinkConfig, endpoint: &str) {
+    let started = Instant::now();
+    let candidates = Url::parse(endpoint)
+        .map_err(anyhow::Error::from)
+        .and_then(|url| match connection_urls(config, &url)? {
+            urls if urls.is_empty() => Err(require_tls_error(&url)),
+            urls => Ok(urls),
+        });
+    // Only a failure to resolve the URLs is worth an attempt of its own
+    let candidates = match candidates {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            let mut attempt = ProbeAttempt::new(endpoint, endpoint);
+            attempt.record::<(), _>("parse_url", started, Err(e));
+            result.attempts.push(attempt);
+            return;
+        }
+    };
+
+    for url in candidates {
+        let mut attempt = ProbeAttempt::new(endpoint, url.as_str());
+        probe_url(&mut attempt, config, &url).await;
+        let connected = attempt.is_success();
+        result.attempts.push(attempt);
+        if connected {
+            break;
+        }
+    }
+}
+
+/// Run the connection phases against a single URL
+async fn probe_url(attempt: &mut ProbeAttempt, config: &LinkConfig, url: &Url) {
+    let started = Instant::now();
+    let Some(tcp) = attempt.record("tcp_connect", started, connect_tcp(config, url).await) else {
+        return;
+    };
+
+    // TLS and the upgrade share the handshake timeout, as they do in the client
+    let handshake_timeout = config.handshake_timeout();
+    let deadline = tokio::time::Instant::now() + handshake_timeout;
+
+    if url.scheme() == "wss" {
+        let started = Instant::now();
+        let handshake = async {
+            let host = url.host_str().unwrap_or_default().to_string();
+            let server_name = ServerName::try_from(host).map_err(|e| e.to_string())?;
+            let tls_config = tls_client_config(config).map_err(|e| e.to_string())?;
+            match timeout_at(
+                deadline,
+                TlsConnector::from(tls_config).connect(server_name, tcp),
+            )
+            .await
+            {
+                Ok(tls) => tls.map_err(|e| e.to_string()),
+                Err(_) => Err(handshake_timeout_error(handshake_timeout).to_string()),
+            }
+        }
+        .await;
+        let Some(tls) = attempt.record("tls_handshake", started, handshake) else {
+            return;
+        };
+        upgrade_and_close(attempt, config, url, tls, deadline).await;
+    } else {
+        upgrade_and_close(attempt, config, url, tcp, deadline).await;
+    }
+}
+
+/// Perform the WebSocket upgrade over an established stream, then close it
+async fn upgrade_and_close<S>(
+    attempt: &mut ProbeAttempt,
+    config: &LinkConfig,
+    url: &Url,
+    stream: S,
+    deadline: tokio::time::Instant,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let started = Instant::now();
+    let upgrade = match handshake_request(config, url) {
+        Ok(request) => {
+            match timeout_at(deadline, tokio_tungstenite::client_async(request, stream)).await {
+                Ok(upgrade) => upgrade.map_err(|e| e.to_string()),
+                Err(_) => Err(handshake_timeout_error(config.handshake_timeout()).to_string()),
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    let Some((mut ws_stream, _)) = attempt.record("websocket_upgrade", started, upgrade) else {
+        return;
+    };
+
+    let started = Instant::now();
+    attempt.record("close", started, ws_stream.close(None).await);
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// A WebSocket server accepting connections until the test ends
+    async fn good_endpoint() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    // TLS attempts against this plaintext server fail the upgrade
+                    if let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await {
+                        while ws.next().await.is_some() {}
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    /// A TCP server that accepts connections but never answers the upgrade
+    async fn silent_endpoint() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            loop {
+                streams.push(listener.accept().await.unwrap());
+            }
+        });
+        url
+    }
+
+    fn phase_names(attempt: &ProbeAttempt) -> Vec<&str> {
+        attempt.phases.iter().map(|phase| phase.name).collect()
+    }
+
+    #[tokio::test]
+    async fn good_endpoint_passes_every_phase() {
+        let url = good_endpoint().await;
+        let config = LinkConfig::builder(url.as_str()).build().unwrap();
+
+        let result = probe_connection(&config).await;
+        assert!(result.is_success(), "{}", result);
+        assert_eq!(result.attempts.len(), 1);
+        assert_eq!(
+            phase_names(&result.attempts[0]),
+            ["tcp_connect", "websocket_upgrade", "close"]
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_endpoint_reports_the_failing_phase() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let refused = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let config = LinkConfig::builder(refused).build().unwrap();
+
+        let result = probe_connection(&config).await;
+        assert!(!result.is_success());
+        let tcp = &result.attempts[0].phases[0];
+        assert_eq!(tcp.name, "tcp_connect");
+        assert!(tcp.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn upgrade_is_bounded_by_the_handshake_timeout() {
+        let url = silent_endpoint().await;
+        let config = LinkConfig::builder(url)
+            .set("handshake_timeout_ms", 50)
+            .build()
+            .unwrap();
+
+        let result = probe_connection(&config).await;
+        assert!(!result.is_success());
+        let upgrade = &result.attempts[0].phases[1];
+        assert_eq!(upgrade.name, "websocket_upgrade");
+        assert!(upgrade.error.as_ref().unwrap().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn every_fallback_url_is_probed() {
+        let good = good_endpoint().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let refused = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let config = LinkConfig::builder(good)
+            .set("websocket_urls", &refused)
+            .build()
+            .unwrap();
+
+        let result = probe_connection(&config).await;
+        assert!(!result.is_success());
+        assert!(result
+            .attempts
+            .iter()
+            .any(|attempt| attempt.endpoint == refused && !attempt.is_success()));
+    }
+
+    #[tokio::test]
+    async fn require_tls_never_probes_in_plaintext() {
+        let url = good_endpoint().await;
+        let config = LinkConfig::builder(url.as_str())
+            .set("auto_upgrade_tls", true)
+            .set("require_tls", true)
+            .set("handshake_timeout_ms", 500)
+            .build()
+            .unwrap();
+
+        let result = probe_connection(&config).await;
+        assert!(!result.is_success());
+        assert!(result
+            .attempts
+            .iter()
+            .all(|attempt| attempt.url.starts_with("wss://")));
+
+        // Without require_tls the failed upgrade falls back to plaintext
+        let config = LinkConfig::builder(url)
+            .set("auto_upgrade_tls", true)
+            .set("handshake_timeout_ms", 500)
+            .build()
+            .unwrap();
+        let result = probe_connection(&config).await;
+        assert!(result.is_success(), "{}", result);
+        assert_eq!(result.attempts.len(), 2);
+    }
+}
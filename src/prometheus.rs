@@ -0,0 +1,128 @@
+//! Optional Prometheus metrics endpoint
+//!
+//! Enabled with the `prometheus` feature and the
+//! `WEBSOCKET_PROVIDER_METRICS_PORT` environment variable. Counters are
+//! process-wide, summed over all connections.
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+use tracing::info;
+
+use crate::provider::WebSocketProvider;
+
+/// Environment variable holding the port to serve `/metrics` on
+pub const METRICS_PORT_ENV: &str = "WEBSOCKET_PROVIDER_METRICS_PORT";
+
+static MESSAGES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+
+/// Count one received data message of `size` bytes
+pub fn record_message(size: usize) {
+    MESSAGES_RECEIVED.fetch_add(1, Ordering::Relaxed);
+    BYTES_RECEIVED.fetch_add(size as u64, Ordering::Relaxed);
+}
+
+/// Count one reconnection attempt
+pub fn record_reconnect() {
+    RECONNECTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Render all metrics in the Prometheus text exposition format
+pub fn render(active_connections: usize) -> String {
+    let mut out = String::new();
+    let counters = [
+        (
+            "websocket_messages_received_total",
+            "Data messages received from WebSocket servers",
+            &MESSAGES_RECEIVED,
+        ),
+        (
+            "websocket_bytes_received_total",
+            "Payload bytes received from WebSocket servers",
+            &BYTES_RECEIVED,
+        ),
+        (
+            "websocket_reconnects_total",
+            "Reconnection attempts after a failed or lost connection",
+            &RECONNECTS,
+        ),
+    ];
+    for (name, help, value) in counters {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+    }
+    let _ = writeln!(
+        out,
+        "# HELP websocket_active_connections Linked connections whose client is still running"
+    );
+    let _ = writeln!(out, "# TYPE websocket_active_connections gauge");
+    let _ = writeln!(out, "websocket_active_connections {}", active_connections);
+    out
+}
+
+/// Serve `/metrics` on `0.0.0.0:<port>` until the process exits
+pub async fn serve(provider: WebSocketProvider, port: u16) -> anyhow::Result<()> {
+    let router = Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(provider);
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    info!("Metrics endpoint listening on {}", listener.local_addr()?);
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+async fn metrics(State(provider): State<WebSocketProvider>) -> String {
+    let stats = provider.stats().await;
+    render(stats.connections - stats.finished_tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Value of a metric in rendered output
+    fn value(rendered: &str, name: &str) -> u64 {
+        rendered
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn render_exposes_every_metric() {
+        let rendered = render(3);
+        for name in [
+            "websocket_messages_received_total",
+            "websocket_bytes_received_total",
+            "websocket_reconnects_total",
+        ] {
+            assert!(rendered.contains(&format!("# TYPE {} counter", name)));
+        }
+        assert!(rendered.contains("# TYPE websocket_active_connections gauge"));
+        assert_eq!(value(&rendered, "websocket_active_connections"), 3);
+    }
+
+    #[test]
+    fn recorded_messages_are_counted() {
+        // Counters are process-wide, so other tests may add to them too
+        let before = render(0);
+        record_message(10);
+        record_reconnect();
+        let after = render(0);
+        for (name, delta) in [
+            ("websocket_messages_received_total", 1),
+            ("websocket_bytes_received_total", 10),
+            ("websocket_reconnects_total", 1),
+        ] {
+            assert!(value(&after, name) >= value(&before, name) + delta);
+        }
+    }
+}
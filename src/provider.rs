@@ -1,22 +1,32 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context as _;
-use tokio::sync::RwLock;
-use tracing::{error, info, warn};
+use bytes::Bytes;
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio_util::task::TaskTracker;
+use tracing::{debug, error, info, warn};
 use wasmcloud_provider_sdk::initialize_observability;
 use wasmcloud_provider_sdk::{
-    run_provider, LinkConfig as SdkLinkConfig, LinkDeleteInfo, Provider, ProviderInitConfig,
+    run_provider, serve_provider_exports, Context, HealthCheckRequest, HealthCheckResponse,
+    LinkConfig as SdkLinkConfig, LinkDeleteInfo, Provider, ProviderInitConfig,
 };
 
-use crate::config::{LinkConfig, ProviderConfig};
-use crate::websocket::WebSocketClient;
+use crate::config::{validate_subject, HostLimitPolicy, LinkConfig, ProviderConfig};
+use crate::message::{MessageType, WebSocketMessage};
+use crate::metrics::MetricsSnapshot;
+use crate::probe::probe_connection;
+use crate::timeline;
+use crate::websocket::{ConnectionStatus, WebSocketClient};
 
 pub(crate) mod bindings {
     wit_bindgen_wrpc::generate!({
         with: {
             "wasmcloud:messaging/types@0.2.0": generate,
             "wasmcloud:messaging/handler@0.2.0": generate,
+            "wasmcloud:messaging/consumer@0.2.0": generate,
         }
     });
 }
@@ -28,9 +38,34 @@ use bindings::wasmcloud::messaging::types;
 /// State for a single WebSocket connection
 struct ConnectionState {
     /// Configuration for this connection
-    _config: LinkConfig,
+    config: LinkConfig,
     /// Handle to the WebSocket task
     _task_handle: tokio::task::JoinHandle<()>,
+    /// Client driving the connection, kept for diagnostics and draining
+    client: Arc<WebSocketClient>,
+    /// The client task and its in-flight sends to the component
+    tasks: TaskTracker,
+    /// When the link was established
+    connected_at: Instant,
+    /// Subject text data messages are published to, with the provider's prefix
+    subject: String,
+}
+
+/// A connection slot for an upstream host
+enum HostSlot {
+    /// Slot already taken, held for the lifetime of the connection
+    Acquired(OwnedSemaphorePermit),
+    /// Host was full; wait for a slot before connecting
+    Queued(Arc<Semaphore>),
+}
+
+impl HostSlot {
+    async fn acquire(self) -> Option<OwnedSemaphorePermit> {
+        match self {
+            HostSlot::Acquired(permit) => Some(permit),
+            HostSlot::Queued(semaphore) => semaphore.acquire_owned().await.ok(),
+        }
+    }
 }
 
 /// WebSocket provider implementation
@@ -39,6 +74,8 @@ pub struct WebSocketProvider {
     config: Arc<RwLock<ProviderConfig>>,
     /// All components linked to this provider (target) and their connections
     connections: Arc<RwLock<HashMap<String, ConnectionState>>>,
+    /// Connection slots per upstream host, when `max_connections_per_host` is set
+    host_slots: Arc<RwLock<HashMap<String, Arc<Semaphore>>>>,
 }
 
 impl WebSocketProvider {
@@ -48,53 +85,133 @@ impl WebSocketProvider {
 
     /// Execute the provider
     pub async fn run() -> anyhow::Result<()> {
+        crate::logging::apply_log_filter();
         initialize_observability!(
             Self::name(),
             std::env::var_os("PROVIDER_WEBSOCKET_FLAMEGRAPH_PATH")
         );
 
         let provider = Self::default();
+        #[cfg(feature = "prometheus")]
+        provider.start_metrics_endpoint()?;
         let shutdown = run_provider(provider.clone(), Self::name())
             .await
             .context("failed to run provider")?;
 
-        // For this unidirectional provider, we don't export any functions
-        // Just await shutdown
-        shutdown.await;
-        Ok(())
+        // Serve the outbound interface so components can send frames
+        let connection = wasmcloud_provider_sdk::get_connection();
+        serve_provider_exports(
+            &connection
+                .get_wrpc_client(connection.provider_key())
+                .await
+                .context("failed to get wrpc client")?,
+            provider,
+            shutdown,
+            bindings::serve,
+        )
+        .await
+        .context("failed to serve provider exports")
     }
-}
 
-/// Implement the Provider trait for wasmCloud integration
-impl Provider for WebSocketProvider {
-    /// Initialize the provider
-    async fn init(&self, config: impl ProviderInitConfig) -> anyhow::Result<()> {
-        let provider_id = config.get_provider_id();
-        let initial_config = config.get_config();
-        info!(
-            provider_id,
-            ?initial_config,
-            "initializing WebSocket provider"
-        );
+    /// Serve the HTTP admin API in the background
+    #[cfg(feature = "rest-admin")]
+    fn start_admin_api(&self, port: u16) {
+        let provider = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::admin::serve(provider, port).await {
+                error!("Admin API failed: {}", e);
+            }
+        });
+    }
 
-        // Save configuration to provider state
-        *self.config.write().await = ProviderConfig::from(initial_config);
+    #[cfg(not(feature = "rest-admin"))]
+    fn start_admin_api(&self, _port: u16) {
+        warn!("admin_port is set but the provider was built without the `rest-admin` feature");
+    }
 
+    /// Serve Prometheus metrics in the background when a metrics port is set
+    #[cfg(feature = "prometheus")]
+    fn start_metrics_endpoint(&self) -> anyhow::Result<()> {
+        let Ok(port) = std::env::var(crate::prometheus::METRICS_PORT_ENV) else {
+            return Ok(());
+        };
+        let port: u16 = port.trim().parse().map_err(|e| {
+            anyhow::anyhow!("Invalid {}: {}", crate::prometheus::METRICS_PORT_ENV, e)
+        })?;
+        let provider = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::prometheus::serve(provider, port).await {
+                error!("Metrics endpoint failed: {}", e);
+            }
+        });
         Ok(())
     }
 
-    /// Handle incoming link from a component (component links TO this provider)
-    /// This is where we start the WebSocket client
-    async fn receive_link_config_as_target(
+    /// Reserve a connection slot for the link's upstream host, if hosts are limited
+    async fn reserve_host_slot(
         &self,
-        SdkLinkConfig {
-            source_id, config, ..
-        }: SdkLinkConfig<'_>,
+        link_config: &LinkConfig,
+    ) -> anyhow::Result<Option<HostSlot>> {
+        let provider_config = self.config.read().await.clone();
+        let Some(limit) = provider_config.max_connections_per_host()? else {
+            return Ok(None);
+        };
+        let host = link_config.host().unwrap_or_default();
+
+        let semaphore = self
+            .host_slots
+            .write()
+            .await
+            .entry(host.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+            .clone();
+
+        match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Ok(Some(HostSlot::Acquired(permit))),
+            Err(_) => match provider_config.host_limit_policy()? {
+                HostLimitPolicy::Reject => {
+                    anyhow::bail!("Connection limit ({}) reached for host {}", limit, host)
+                }
+                HostLimitPolicy::Queue => {
+                    warn!(
+                        "Connection limit ({}) reached for host {}, queueing link",
+                        limit, host
+                    );
+                    Ok(Some(HostSlot::Queued(semaphore)))
+                }
+            },
+        }
+    }
+
+    /// Start the WebSocket client for a linked component and track its state
+    pub(crate) async fn start_connection(
+        &self,
+        source_id: &str,
+        link_config: LinkConfig,
     ) -> anyhow::Result<()> {
-        info!("Received link configuration from component: {}", source_id);
+        if link_config.probe_before_link {
+            // Every phase is bounded by the link's connect and handshake timeouts
+            let probe = probe_connection(&link_config).await;
+            if !probe.is_success() {
+                anyhow::bail!("Connection probe failed: {}", probe);
+            }
+            info!("Connection probe succeeded: {}", probe);
+        }
 
-        // Parse link configuration
-        let link_config = LinkConfig::from_values(config)?;
+        // A link put again replaces its running connection, whose task must
+        // have released its host slot before a slot is asked for again
+        if let Some(state) = self.remove_connection(source_id).await {
+            let _ = state._task_handle.await;
+        }
+
+        let host_slot = self.reserve_host_slot(&link_config).await?;
+        let subject_prefix = self.config.read().await.subject_prefix()?;
+        let with_prefix = |subject: &str| match &subject_prefix {
+            Some(prefix) => format!("{}.{}", prefix, subject),
+            None => subject.to_string(),
+        };
+        let dead_letter_subject = link_config.dead_letter_subject.as_deref().map(with_prefix);
+        let subject = with_prefix(&link_config.data_subject(MessageType::Text));
 
         info!(
             "Starting WebSocket client for URL: {}",
@@ -105,24 +222,114 @@ impl Provider for WebSocketProvider {
         let config_clone = link_config.clone();
         let source_id_clone = source_id.to_string();
 
+        let client = Arc::new(WebSocketClient::new(link_config.clone()).with_source_id(source_id));
+        let ws_client = client.clone();
+        let tasks = TaskTracker::new();
+        let send_tasks = tasks.clone();
+        // Bounds messages awaiting delivery so a slow component can't grow memory
+        let buffer_size = link_config.message_buffer_size;
+        let in_flight = Arc::new(Semaphore::new(buffer_size));
+        let mut dropped = 0u64;
+
         // Spawn WebSocket client task
-        let task_handle = tokio::spawn(async move {
-            let ws_client = WebSocketClient::new(config_clone.clone());
+        let task_handle = tasks.spawn(async move {
+            // Hold the host slot until the task ends or is aborted
+            let _host_permit = match host_slot {
+                Some(slot) => tokio::select! {
+                    permit = slot.acquire() => match permit {
+                        Some(permit) => Some(permit),
+                        None => return,
+                    },
+                    _ = ws_client.drain_started() => return,
+                },
+                None => None,
+            };
 
             // Create message handler that forwards to the component via wRPC
             // using the standard wasmcloud:messaging interface
-            let ws_url = config_clone.websocket_url.clone();
+            let counters = ws_client.clone();
             let result = ws_client
-                .run(move |data| {
+                .run(move |mut message| {
+                    debug!(
+                        "Forwarding {} message: {} bytes",
+                        message.message_type,
+                        message.payload.len()
+                    );
+
+                    // Consumers only see the body, so embed connection metadata there
+                    message.embed_connection();
+
+                    if let Some(compression) = &config_clone.compression {
+                        message.payload = match crate::compression::maybe_compress(
+                            message.payload,
+                            compression,
+                        ) {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                error!("Dropping message that failed to compress: {}", e);
+                                counters.record_dropped();
+                                return Ok(());
+                            }
+                        };
+                    }
+
                     // Convert WebSocket message to a standard broker-message
-                    let message = create_broker_message(data, &ws_url);
+                    let message = match create_broker_message(
+                        message,
+                        &config_clone,
+                        subject_prefix.as_deref(),
+                    ) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            error!("Dropping message with invalid subject: {}", e);
+                            counters.record_dropped();
+                            return Ok(());
+                        }
+                    };
+
+                    let Ok(permit) = in_flight.clone().try_acquire_owned() else {
+                        dropped += 1;
+                        counters.record_dropped();
+                        warn!(
+                            "Forwarding buffer full ({} messages in flight), dropped {} messages",
+                            buffer_size, dropped
+                        );
+                        return Ok(());
+                    };
 
                     // Spawn a task to send message to component
                     let source = source_id_clone.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = send_message_to_component(&source, message).await {
-                            error!("Failed to send message to component {}: {}", source, e);
+                    let counters = counters.clone();
+                    let dead_letter_subject = dead_letter_subject.clone();
+                    send_tasks.spawn(async move {
+                        let _permit = permit;
+                        // Bodies are refcounted, so keeping the original is cheap
+                        let original = dead_letter_subject
+                            .is_some()
+                            .then(|| (message.subject.clone(), message.body.clone()));
+                        let e = match send_message_to_component(&source, message).await {
+                            Ok(()) => {
+                                counters.record_forwarded();
+                                return;
+                            }
+                            Err(e) => e,
+                        };
+                        error!("Failed to send message to component {}: {}", source, e);
+
+                        if let (Some(subject), Some((original_subject, body))) =
+                            (dead_letter_subject, original)
+                        {
+                            let message =
+                                dead_letter_message(subject, &original_subject, &body, &e);
+                            match send_message_to_component(&source, message).await {
+                                Ok(()) => return,
+                                Err(e) => error!(
+                                    "Failed to send dead letter to component {}: {}",
+                                    source, e
+                                ),
+                            }
                         }
+                        counters.record_dropped();
                     });
 
                     Ok(())
@@ -131,6 +338,10 @@ impl Provider for WebSocketProvider {
 
             if let Err(e) = result {
                 error!("WebSocket client error: {}", e);
+                debug!(
+                    "Connection timeline: {}",
+                    timeline::to_json(&ws_client.timeline())
+                );
             }
         });
 
@@ -138,11 +349,423 @@ impl Provider for WebSocketProvider {
         self.connections.write().await.insert(
             source_id.to_string(),
             ConnectionState {
-                _config: link_config,
+                config: link_config,
                 _task_handle: task_handle,
+                client,
+                tasks,
+                connected_at: Instant::now(),
+                subject,
             },
         );
 
+        Ok(())
+    }
+
+    /// Drain a component's connection, returning whether one existed
+    ///
+    /// New frames stop being forwarded and the WebSocket is closed gracefully.
+    /// Messages already on their way to the component get up to the link's
+    /// `drain_timeout_secs` to be delivered before the connection is removed.
+    pub async fn drain(&self, source_id: &str) -> bool {
+        let Some((client, tasks, drain_timeout)) =
+            self.connections.read().await.get(source_id).map(|state| {
+                (
+                    state.client.clone(),
+                    state.tasks.clone(),
+                    state.config.drain_timeout(),
+                )
+            })
+        else {
+            return false;
+        };
+
+        info!("Draining WebSocket connection for component: {}", source_id);
+        client.drain();
+        tasks.close();
+        if tokio::time::timeout(drain_timeout, tasks.wait())
+            .await
+            .is_err()
+        {
+            warn!(
+                "Drain of component {} timed out after {:?} with {} tasks still delivering",
+                source_id,
+                drain_timeout,
+                tasks.len()
+            );
+        }
+        self.disconnect(source_id).await;
+        true
+    }
+
+    /// Stop a component's WebSocket connection, returning whether one existed
+    pub async fn disconnect(&self, source_id: &str) -> bool {
+        self.remove_connection(source_id).await.is_some()
+    }
+
+    /// Remove a component's connection state and abort its task
+    async fn remove_connection(&self, source_id: &str) -> Option<ConnectionState> {
+        // Remove connection state (task will be cancelled)
+        let state = self.connections.write().await.remove(source_id)?;
+        info!("WebSocket connection closed for component: {}", source_id);
+        state._task_handle.abort();
+        debug!(
+            "Connection timeline: {}",
+            timeline::to_json(&state.client.timeline())
+        );
+        Some(state)
+    }
+
+    /// IDs of all linked components, oldest connection first
+    pub async fn active_connection_ids(&self) -> Vec<String> {
+        let connections = self.connections.read().await;
+        let mut ids: Vec<(&String, Instant)> = connections
+            .iter()
+            .map(|(source_id, state)| (source_id, state.connected_at))
+            .collect();
+        ids.sort_by_key(|(_, connected_at)| *connected_at);
+        ids.into_iter()
+            .map(|(source_id, _)| source_id.clone())
+            .collect()
+    }
+}
+
+/// Snapshot of a single connection, for introspection
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub source_id: String,
+    pub websocket_url: String,
+    /// Subject text data messages are published to, before any `subject_template`
+    pub subject: String,
+    /// Seconds since the link was established
+    pub connected_secs: u64,
+    /// Whether the client task has exited (gave up or failed)
+    pub task_is_finished: bool,
+    /// Data messages received since the link was established
+    pub messages_received: u64,
+    /// Messages per second, averaged over the last minute
+    pub messages_per_second: f64,
+    /// Times the server violated the WebSocket protocol
+    pub protocol_violations_total: u64,
+    /// Messages dropped for exceeding `max_message_size`
+    pub oversized_messages_total: u64,
+    /// Where the client is in its connection lifecycle
+    pub status: ConnectionStatus,
+}
+
+/// Provider-wide connection counters
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderStats {
+    pub connections: usize,
+    pub connected: usize,
+    pub finished_tasks: usize,
+    /// Clients that gave up after an error, excluding ones that closed normally
+    pub failed: usize,
+}
+
+/// Introspection and management used by the admin API and the control interface
+impl WebSocketProvider {
+    /// Snapshot of all connections, oldest first
+    pub async fn list_connections(&self) -> Vec<ConnectionInfo> {
+        let connections = self.connections.read().await;
+        let mut infos: Vec<(Instant, ConnectionInfo)> = connections
+            .iter()
+            .map(|(source_id, state)| {
+                let (messages_received, messages_per_second) = state.client.message_stats();
+                (
+                    state.connected_at,
+                    ConnectionInfo {
+                        source_id: source_id.clone(),
+                        websocket_url: state.client.current_url(),
+                        subject: state.subject.clone(),
+                        connected_secs: state.connected_at.elapsed().as_secs(),
+                        task_is_finished: state._task_handle.is_finished(),
+                        messages_received,
+                        messages_per_second,
+                        protocol_violations_total: state.client.protocol_violations_total(),
+                        oversized_messages_total: state.client.oversized_messages_total(),
+                        status: state.client.status(),
+                    },
+                )
+            })
+            .collect();
+        infos.sort_by_key(|(connected_at, _)| *connected_at);
+        infos.into_iter().map(|(_, info)| info).collect()
+    }
+
+    /// Connection status of every component, keyed by source ID
+    pub async fn connection_statuses(&self) -> HashMap<String, ConnectionStatus> {
+        self.connections
+            .read()
+            .await
+            .iter()
+            .map(|(source_id, state)| (source_id.clone(), state.client.status()))
+            .collect()
+    }
+
+    /// Delivery counters of a component's connection
+    pub async fn get_metrics(&self, source_id: &str) -> Option<MetricsSnapshot> {
+        self.connections
+            .read()
+            .await
+            .get(source_id)
+            .map(|state| state.client.metrics())
+    }
+
+    /// Link configuration of a component's connection
+    pub async fn connection_config(&self, source_id: &str) -> Option<LinkConfig> {
+        self.connections
+            .read()
+            .await
+            .get(source_id)
+            .map(|state| state.config.clone())
+    }
+
+    /// Timeline of a component's connection as JSON, oldest event first
+    pub async fn diagnostics(&self, source_id: &str) -> Option<serde_json::Value> {
+        self.connections
+            .read()
+            .await
+            .get(source_id)
+            .map(|state| timeline::to_json(&state.client.timeline()))
+    }
+
+    /// Restart a component's connection with its current configuration
+    pub async fn reconnect(&self, source_id: &str) -> anyhow::Result<()> {
+        let link_config = self
+            .connection_config(source_id)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("No connection found for component: {}", source_id))?;
+        // Replaces the running connection, handing its host slot over
+        self.start_connection(source_id, link_config).await
+    }
+
+    /// Provider-wide connection counters
+    pub async fn stats(&self) -> ProviderStats {
+        let connections = self.connections.read().await;
+        ProviderStats {
+            connections: connections.len(),
+            connected: connections
+                .values()
+                .filter(|state| state.client.status() == ConnectionStatus::Connected)
+                .count(),
+            finished_tasks: connections
+                .values()
+                .filter(|state| state._task_handle.is_finished())
+                .count(),
+            failed: connections
+                .values()
+                .filter(|state| state.client.status() == ConnectionStatus::Failed)
+                .count(),
+        }
+    }
+}
+
+impl WebSocketProvider {
+    /// Queue a frame on the calling component's own connection
+    async fn send_from_component(
+        &self,
+        cx: Option<Context>,
+        data: Vec<u8>,
+        is_binary: bool,
+    ) -> Result<(), String> {
+        let (source_id, client) = self.caller_connection(cx).await?;
+        debug!(
+            "Queueing {} byte outbound frame for component {}",
+            data.len(),
+            source_id
+        );
+        client
+            .send_message(data, is_binary)
+            .map_err(|e| e.to_string())
+    }
+
+    /// The calling component's ID and the client driving its connection
+    async fn caller_connection(
+        &self,
+        cx: Option<Context>,
+    ) -> Result<(String, Arc<WebSocketClient>), String> {
+        let Some(source_id) = cx.and_then(|cx| cx.component) else {
+            return Err("missing component ID in invocation context".to_string());
+        };
+        let client = self
+            .connections
+            .read()
+            .await
+            .get(&source_id)
+            .map(|state| state.client.clone());
+        match client {
+            Some(client) => Ok((source_id, client)),
+            None => Err(format!("No connection found for component: {}", source_id)),
+        }
+    }
+}
+
+/// Outbound frames from linked components, sent on the caller's own connection
+impl bindings::exports::wasmcloud::websocket::outbound::Handler<Option<Context>>
+    for WebSocketProvider
+{
+    async fn send_to_websocket(
+        &self,
+        cx: Option<Context>,
+        data: Bytes,
+        is_binary: bool,
+    ) -> anyhow::Result<Result<(), String>> {
+        Ok(self.send_from_component(cx, data.to_vec(), is_binary).await)
+    }
+}
+
+/// Typed text and binary sends from linked components, on the caller's own connection
+impl bindings::exports::wasmcloud::websocket::sender::Handler<Option<Context>>
+    for WebSocketProvider
+{
+    async fn send_text(
+        &self,
+        cx: Option<Context>,
+        text: String,
+    ) -> anyhow::Result<Result<(), String>> {
+        Ok(self.send_from_component(cx, text.into_bytes(), false).await)
+    }
+
+    async fn send_binary(
+        &self,
+        cx: Option<Context>,
+        data: Bytes,
+    ) -> anyhow::Result<Result<(), String>> {
+        Ok(self.send_from_component(cx, data.to_vec(), true).await)
+    }
+}
+
+/// Standard messaging publishes from linked components, written to the caller's
+/// own connection
+///
+/// The subject is ignored since each component has a single connection.
+/// Bodies that are valid UTF-8 go out as text frames, anything else as binary.
+/// With `request_reply_mode`, a request's reply is the server's next message.
+impl bindings::exports::wasmcloud::messaging::consumer::Handler<Option<Context>>
+    for WebSocketProvider
+{
+    async fn publish(
+        &self,
+        cx: Option<Context>,
+        msg: types::BrokerMessage,
+    ) -> anyhow::Result<Result<(), String>> {
+        let is_binary = std::str::from_utf8(&msg.body).is_err();
+        Ok(self
+            .send_from_component(cx, msg.body.to_vec(), is_binary)
+            .await)
+    }
+
+    async fn request(
+        &self,
+        cx: Option<Context>,
+        subject: String,
+        body: Bytes,
+        timeout_ms: u32,
+    ) -> anyhow::Result<Result<types::BrokerMessage, String>> {
+        let (source_id, client) = match self.caller_connection(cx).await {
+            Ok(caller) => caller,
+            Err(e) => return Ok(Err(e)),
+        };
+        let config = client.config();
+        if !config.request_reply_mode {
+            return Ok(Err(
+                "request is not supported: set request_reply_mode to pair requests with the next message".to_string(),
+            ));
+        }
+        let reply_timeout = match timeout_ms {
+            0 => config.reply_timeout(),
+            ms => Duration::from_millis(u64::from(ms)),
+        };
+
+        debug!("Sending request for component {}", source_id);
+        let is_binary = std::str::from_utf8(&body).is_err();
+        Ok(client
+            .request(body.to_vec(), is_binary, reply_timeout)
+            .await
+            .map(|reply| types::BrokerMessage {
+                subject,
+                body: reply.payload.into(),
+                reply_to: None,
+            })
+            .map_err(|e| e.to_string()))
+    }
+}
+
+/// Connection introspection for the host
+impl bindings::exports::wasmcloud::websocket::control::Handler<Option<Context>>
+    for WebSocketProvider
+{
+    async fn list_connections(
+        &self,
+        _cx: Option<Context>,
+    ) -> anyhow::Result<Vec<bindings::exports::wasmcloud::websocket::control::ConnectionInfo>> {
+        Ok(WebSocketProvider::list_connections(self)
+            .await
+            .into_iter()
+            .map(
+                |info| bindings::exports::wasmcloud::websocket::control::ConnectionInfo {
+                    source_id: info.source_id,
+                    websocket_url: info.websocket_url,
+                    subject: info.subject,
+                    task_is_finished: info.task_is_finished,
+                    messages_received: info.messages_received,
+                },
+            )
+            .collect())
+    }
+}
+
+/// Implement the Provider trait for wasmCloud integration
+impl Provider for WebSocketProvider {
+    /// Initialize the provider
+    async fn init(&self, config: impl ProviderInitConfig) -> anyhow::Result<()> {
+        let provider_id = config.get_provider_id();
+        let initial_config = config.get_config();
+        info!(
+            provider_id,
+            ?initial_config,
+            "initializing WebSocket provider"
+        );
+
+        // Values from an optional config file are overridden by init config
+        let provider_config = match initial_config.get("config_file") {
+            Some(path) => ProviderConfig::from_file_and_values(path, initial_config)?,
+            None => ProviderConfig::from(initial_config),
+        };
+
+        // Fail fast rather than on the first forwarded message or link
+        provider_config.validate()?;
+
+        if let Some(port) = provider_config.admin_port()? {
+            self.start_admin_api(port);
+        }
+
+        // Save configuration to provider state
+        *self.config.write().await = provider_config;
+
+        Ok(())
+    }
+
+    /// Handle incoming link from a component (component links TO this provider)
+    /// This is where we start the WebSocket client
+    async fn receive_link_config_as_target(
+        &self,
+        SdkLinkConfig {
+            source_id, config, ..
+        }: SdkLinkConfig<'_>,
+    ) -> anyhow::Result<()> {
+        info!("Received link configuration from component: {}", source_id);
+
+        // Parse link configuration and enforce the operator's policy
+        let link_config = LinkConfig::from_values(config)?;
+        self.config
+            .read()
+            .await
+            .link_policy()?
+            .check(&link_config)?;
+
+        self.start_connection(source_id, link_config).await?;
+
         info!(
             "WebSocket connection established for component: {}",
             source_id
@@ -155,26 +778,45 @@ impl Provider for WebSocketProvider {
         let source_id = link.get_source_id();
         info!("Deleting link with component: {}", source_id);
 
-        // Remove connection state (task will be cancelled)
-        if let Some(state) = self.connections.write().await.remove(source_id) {
-            info!("WebSocket connection closed for component: {}", source_id);
-            state._task_handle.abort();
-        } else {
+        // Deliver messages already on their way before closing the connection
+        if !self.drain(source_id).await {
             warn!("No connection found for component: {}", source_id);
         }
 
         Ok(())
     }
 
+    /// Report unhealthy while any linked client has failed for good
+    ///
+    /// Clients that are reconnecting are still healthy, and so are ones that
+    /// closed normally or were drained. A failed client stays failed until
+    /// its link is recreated.
+    async fn health_request(
+        &self,
+        _arg: &HealthCheckRequest,
+    ) -> anyhow::Result<HealthCheckResponse> {
+        let stats = self.stats().await;
+        Ok(HealthCheckResponse {
+            healthy: stats.failed == 0,
+            message: Some(format!(
+                "{} of {} connections up, {} failed",
+                stats.connected, stats.connections, stats.failed
+            )),
+        })
+    }
+
     /// Handle provider shutdown
     async fn shutdown(&self) -> anyhow::Result<()> {
         info!("Shutting down WebSocket provider");
 
-        // Clean up all connections
+        // Clean up all connections, oldest first
+        let source_ids = self.active_connection_ids().await;
         let mut connections = self.connections.write().await;
-        for (source_id, state) in connections.drain() {
-            info!("Closing WebSocket connection for component: {}", source_id);
-            state._task_handle.abort();
+        for source_id in source_ids {
+            if let Some(state) = connections.remove(&source_id) {
+                info!("Closing WebSocket connection for component: {}", source_id);
+                state._task_handle.abort();
+            }
         }
 
         info!("WebSocket provider shutdown complete");
@@ -182,15 +824,57 @@ impl Provider for WebSocketProvider {
     }
 }
 
-/// Create a broker-message from raw WebSocket data
+/// Create a broker-message from a received WebSocket message
 ///
 /// The subject is set to "websocket.<url>" so the component knows
-/// which WebSocket connection the message originated from.
+/// which WebSocket connection the message originated from, unless the
+/// link routes that message type to its own subject or the message is a
+/// control message with its own subject.
 /// The body contains the raw bytes of the WebSocket message.
-fn create_broker_message(data: Vec<u8>, websocket_url: &str) -> types::BrokerMessage {
+///
+/// A provider-level `subject_prefix` is prepended last, and the combined
+/// subject is validated.
+fn create_broker_message(
+    message: WebSocketMessage,
+    config: &LinkConfig,
+    subject_prefix: Option<&str>,
+) -> anyhow::Result<types::BrokerMessage> {
+    let subject = message
+        .subject
+        .unwrap_or_else(|| config.message_subject(message.message_type, &message.payload));
+    let subject = match subject_prefix {
+        Some(prefix) => {
+            let subject = format!("{}.{}", prefix, subject);
+            validate_subject(&subject)?;
+            subject
+        }
+        None => subject,
+    };
+    Ok(types::BrokerMessage {
+        subject,
+        body: message.payload.into(),
+        reply_to: None,
+    })
+}
+
+/// Wrap a message the component failed to accept for the dead-letter subject
+///
+/// The original body is base64-encoded, since it may be binary.
+fn dead_letter_message(
+    subject: String,
+    original_subject: &str,
+    body: &[u8],
+    error: &anyhow::Error,
+) -> types::BrokerMessage {
+    let body = serde_json::json!({
+        "websocket_dead_letter": true,
+        "subject": original_subject,
+        "error": error.to_string(),
+        "payload": base64_encode(body),
+    });
     types::BrokerMessage {
-        subject: format!("websocket.{}", websocket_url),
-        body: data.into(),
+        subject,
+        body: body.to_string().into_bytes().into(),
         reply_to: None,
     }
 }
@@ -222,8 +906,188 @@ async fn send_message_to_component(
 }
 
 /// Base64 encode helper
-#[allow(dead_code)]
 fn base64_encode(data: &[u8]) -> String {
     use base64::{engine::general_purpose, Engine as _};
     general_purpose::STANDARD.encode(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A link to a local port nothing listens on, so its client keeps retrying
+    fn unreachable_link() -> LinkConfig {
+        unreachable_link_builder().build().unwrap()
+    }
+
+    fn unreachable_link_builder() -> crate::config::LinkConfigBuilder {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        LinkConfig::builder(format!("ws://127.0.0.1:{}", port))
+            .set("initial_reconnect_delay_ms", 50)
+    }
+
+    async fn provider_with(config: ProviderConfig) -> WebSocketProvider {
+        let provider = WebSocketProvider::default();
+        *provider.config.write().await = config;
+        provider
+    }
+
+    #[tokio::test]
+    async fn reconnect_hands_the_host_slot_over() {
+        let provider = provider_with(
+            ProviderConfig::builder()
+                .set("max_connections_per_host", 1)
+                .build()
+                .unwrap(),
+        )
+        .await;
+        let link = unreachable_link();
+        provider.start_connection("a", link.clone()).await.unwrap();
+        assert!(provider.start_connection("b", link).await.is_err());
+
+        for _ in 0..10 {
+            provider.reconnect("a").await.unwrap();
+        }
+        assert_eq!(provider.active_connection_ids().await, ["a"]);
+        assert!(provider.disconnect("a").await);
+    }
+
+    #[tokio::test]
+    async fn relinking_replaces_the_connection_and_its_host_slot() {
+        let provider = provider_with(
+            ProviderConfig::builder()
+                .set("max_connections_per_host", 1)
+                .build()
+                .unwrap(),
+        )
+        .await;
+        let link = unreachable_link();
+        for _ in 0..10 {
+            provider.start_connection("a", link.clone()).await.unwrap();
+        }
+        assert_eq!(provider.active_connection_ids().await, ["a"]);
+        assert!(provider.start_connection("b", link).await.is_err());
+
+        // Only the last put's connection held the slot
+        assert!(provider.disconnect("a").await);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        provider
+            .start_connection("b", unreachable_link())
+            .await
+            .unwrap();
+        provider.disconnect("b").await;
+    }
+
+    #[tokio::test]
+    async fn connection_info_reports_the_prefixed_subject() {
+        let provider = provider_with(
+            ProviderConfig::builder()
+                .set("subject_prefix", "tenant")
+                .build()
+                .unwrap(),
+        )
+        .await;
+        let link = unreachable_link_builder()
+            .set("subject", "feeds.btc")
+            .build()
+            .unwrap();
+        provider.start_connection("a", link).await.unwrap();
+
+        let connections = provider.list_connections().await;
+        assert_eq!(connections[0].subject, "tenant.feeds.btc");
+        provider.disconnect("a").await;
+    }
+
+    #[tokio::test]
+    async fn active_connection_ids_are_oldest_first() {
+        let provider = WebSocketProvider::default();
+        for source_id in ["c", "a", "b"] {
+            provider
+                .start_connection(source_id, unreachable_link())
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        assert_eq!(provider.active_connection_ids().await, ["c", "a", "b"]);
+
+        provider.disconnect("a").await;
+        assert_eq!(provider.active_connection_ids().await, ["c", "b"]);
+        assert!(!provider.disconnect("a").await);
+        provider.disconnect("b").await;
+        provider.disconnect("c").await;
+    }
+
+    #[tokio::test]
+    async fn health_counts_failed_clients_but_not_closed_ones() {
+        let provider = WebSocketProvider::default();
+        provider
+            .start_connection("closed", unreachable_link())
+            .await
+            .unwrap();
+        let failing = unreachable_link_builder()
+            .set("initial_reconnect_delay_ms", 10)
+            .set("max_reconnect_attempts", 1)
+            .build()
+            .unwrap();
+        provider.start_connection("failed", failing).await.unwrap();
+        provider.connections.read().await["closed"].client.drain();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while provider.stats().await.finished_tasks < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let statuses = provider.connection_statuses().await;
+        assert_eq!(statuses["closed"], ConnectionStatus::Closed);
+        assert_eq!(statuses["failed"], ConnectionStatus::Failed);
+        let health = provider
+            .health_request(&HealthCheckRequest {})
+            .await
+            .unwrap();
+        assert!(!health.healthy);
+        assert!(health.message.unwrap().contains("1 failed"));
+
+        provider.disconnect("failed").await;
+        let health = provider
+            .health_request(&HealthCheckRequest {})
+            .await
+            .unwrap();
+        assert!(health.healthy);
+    }
+
+    #[test]
+    fn create_broker_message_prefixes_every_subject() {
+        let link = LinkConfig::builder("wss://feed.example.com")
+            .set("subject", "feeds.btc")
+            .build()
+            .unwrap();
+
+        let data =
+            create_broker_message(WebSocketMessage::text("{}"), &link, Some("tenant123")).unwrap();
+        assert_eq!(data.subject, "tenant123.feeds.btc");
+        assert_eq!(&data.body[..], b"{}");
+
+        let control = WebSocketMessage::control("feeds.status", "{}");
+        let control = create_broker_message(control, &link, Some("tenant123")).unwrap();
+        assert_eq!(control.subject, "tenant123.feeds.status");
+
+        let plain = create_broker_message(WebSocketMessage::text("{}"), &link, None).unwrap();
+        assert_eq!(plain.subject, "feeds.btc");
+    }
+
+    #[test]
+    fn create_broker_message_rejects_an_invalid_prefixed_subject() {
+        let link = LinkConfig::builder("wss://feed.example.com")
+            .build()
+            .unwrap();
+        let control = WebSocketMessage::control("bad subject", "{}");
+        assert!(create_broker_message(control, &link, Some("tenant123")).is_err());
+    }
+}
@@ -0,0 +1,454 @@
+//! Tunnelling outbound connections through an HTTP or SOCKS5 proxy
+//!
+//! `http://` proxies are asked to open a tunnel with `CONNECT`, and
+//! `socks5://`/`socks5h://` proxies get a SOCKS5 handshake. Either way the
+//! result is a plain TCP stream to the WebSocket server, so TLS and the
+//! WebSocket upgrade run over the tunnel exactly as over a direct connection.
+
+use std::net::IpAddr;
+
+use base64::{engine::general_purpose, Engine as _};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::warn;
+use url::Url;
+
+use crate::error::ProviderError;
+
+/// Upper bound on the size of a proxy's CONNECT response head
+const MAX_RESPONSE_HEAD: usize = 8 * 1024;
+
+/// Schemes accepted for `proxy_url`
+pub const PROXY_SCHEMES: [&str; 3] = ["http", "socks5", "socks5h"];
+
+/// Proxy from the environment for connections to `target`, if any
+///
+/// `wss://` targets use `HTTPS_PROXY` and `ws://` targets `HTTP_PROXY`, with
+/// `WS_PROXY` as the fallback for both; lowercase names work too. Hosts
+/// matched by `NO_PROXY` connect directly. A value without a scheme is
+/// taken as an `http://` proxy.
+pub fn from_env(target: &Url) -> Option<Url> {
+    proxy_for(target, env_var)
+}
+
+/// Proxy for `target` given a lookup of the (uppercase) proxy variables
+fn proxy_for(target: &Url, var: impl Fn(&str) -> Option<String>) -> Option<Url> {
+    let host = target.host_str()?;
+    if var("NO_PROXY").is_some_and(|no_proxy| bypasses(&no_proxy, host)) {
+        return None;
+    }
+    let scheme_var = if target.scheme() == "wss" {
+        "HTTPS_PROXY"
+    } else {
+        "HTTP_PROXY"
+    };
+    let (name, value) = [scheme_var, "WS_PROXY"]
+        .into_iter()
+        .find_map(|name| var(name).map(|value| (name, value)))?;
+
+    let value = if value.contains("://") {
+        value
+    } else {
+        format!("http://{}", value)
+    };
+    match Url::parse(&value) {
+        Ok(url) if PROXY_SCHEMES.contains(&url.scheme()) => Some(url),
+        _ => {
+            warn!("Ignoring {}: not an http:// or socks5:// proxy URL", name);
+            None
+        }
+    }
+}
+
+/// Non-empty value of an environment variable, checking the lowercase name first
+fn env_var(name: &str) -> Option<String> {
+    [name.to_ascii_lowercase(), name.to_string()]
+        .into_iter()
+        .find_map(|name| std::env::var(name).ok())
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| value.trim().to_string())
+}
+
+/// Whether a `NO_PROXY` list exempts `host`
+///
+/// Entries match the host itself and its subdomains; `*` matches everything.
+fn bypasses(no_proxy: &str, host: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*"
+                || host.eq_ignore_ascii_case(entry)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+        })
+}
+
+/// Open a TCP stream to `host:port` through the proxy at `proxy_url`
+pub async fn connect_via(proxy_url: &Url, host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    let proxy_host = proxy_url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("proxy_url has no host"))?;
+    let proxy_port = proxy_url.port_or_known_default().unwrap_or(1080);
+    let mut stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+
+    match proxy_url.scheme() {
+        "http" => http_connect(&mut stream, proxy_url, host, port).await?,
+        "socks5" | "socks5h" => socks5_connect(&mut stream, proxy_url, host, port).await?,
+        other => anyhow::bail!("Unsupported proxy scheme: {}", other),
+    }
+    Ok(stream)
+}
+
+/// Proxy credentials from the URL's userinfo, if any
+fn credentials(proxy_url: &Url) -> Option<(&str, &str)> {
+    (!proxy_url.username().is_empty())
+        .then(|| (proxy_url.username(), proxy_url.password().unwrap_or("")))
+}
+
+/// Error for a proxy that refused to open the tunnel
+fn refused(proxy_url: &Url, reason: impl Into<String>) -> anyhow::Error {
+    ProviderError::ProxyRefused {
+        proxy: format!(
+            "{}://{}",
+            proxy_url.scheme(),
+            proxy_url.host_str().unwrap_or_default()
+        ),
+        reason: reason.into(),
+    }
+    .into()
+}
+
+/// Ask an HTTP proxy to open a tunnel with `CONNECT`
+async fn http_connect(
+    stream: &mut TcpStream,
+    proxy_url: &Url,
+    host: &str,
+    port: u16,
+) -> anyhow::Result<()> {
+    let authority = format!("{}:{}", host, port);
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some((user, password)) = credentials(proxy_url) {
+        let token = general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so nothing past the response head is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD {
+            return Err(refused(proxy_url, "CONNECT response head too large"));
+        }
+        let mut byte = [0u8];
+        if stream.read(&mut byte).await? == 0 {
+            return Err(refused(proxy_url, "connection closed during CONNECT"));
+        }
+        head.push(byte[0]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default();
+    let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        return Err(refused(proxy_url, status_line.to_string()));
+    }
+    Ok(())
+}
+
+/// Open a tunnel with a SOCKS5 handshake (RFC 1928), letting the proxy resolve names
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    proxy_url: &Url,
+    host: &str,
+    port: u16,
+) -> anyhow::Result<()> {
+    let credentials = credentials(proxy_url);
+
+    // Offer no-auth, plus username/password when credentials are configured
+    let greeting: &[u8] = if credentials.is_some() {
+        &[5, 2, 0, 2]
+    } else {
+        &[5, 1, 0]
+    };
+    stream.write_all(greeting).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    match (choice, credentials) {
+        ([5, 0], _) => {}
+        ([5, 2], Some((user, password))) => {
+            // Username/password sub-negotiation (RFC 1929)
+            let (user, password) = (user.as_bytes(), password.as_bytes());
+            let (Ok(user_len), Ok(password_len)) =
+                (u8::try_from(user.len()), u8::try_from(password.len()))
+            else {
+                anyhow::bail!("SOCKS5 username and password must be at most 255 bytes");
+            };
+            let mut auth = vec![1, user_len];
+            auth.extend_from_slice(user);
+            auth.push(password_len);
+            auth.extend_from_slice(password);
+            stream.write_all(&auth).await?;
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0 {
+                return Err(refused(proxy_url, "authentication failed"));
+            }
+        }
+        _ => return Err(refused(proxy_url, "no acceptable authentication method")),
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host
+        .trim_matches(|c| c == '[' || c == ']')
+        .parse::<IpAddr>()
+    {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let name = u8::try_from(host.len())
+                .map_err(|_| anyhow::anyhow!("Host name too long for SOCKS5: {}", host))?;
+            request.push(3);
+            request.push(name);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(refused(proxy_url, socks5_reply_message(reply[1])));
+    }
+
+    // Skip the bound address and port
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => usize::from(stream.read_u8().await?),
+        other => anyhow::bail!("Invalid SOCKS5 address type: {}", other),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Human-readable SOCKS5 reply code
+fn socks5_reply_message(code: u8) -> String {
+    match code {
+        1 => "general SOCKS server failure".into(),
+        2 => "connection not allowed by ruleset".into(),
+        3 => "network unreachable".into(),
+        4 => "host unreachable".into(),
+        5 => "connection refused".into(),
+        6 => "TTL expired".into(),
+        7 => "command not supported".into(),
+        8 => "address type not supported".into(),
+        other => format!("reply code {}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn proxy(target: &str, vars: &[(&str, &str)]) -> Option<String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        let target = Url::parse(target).unwrap();
+        proxy_for(&target, |name| {
+            vars.get(name).map(|value| value.to_string())
+        })
+        .map(|url| url.to_string())
+    }
+
+    #[test]
+    fn bypasses_matches_hosts_and_subdomains() {
+        let no_proxy = "localhost, .internal.example.com,10.0.0.1";
+        assert!(bypasses(no_proxy, "localhost"));
+        assert!(bypasses(no_proxy, "internal.example.com"));
+        assert!(bypasses(no_proxy, "feed.INTERNAL.example.com"));
+        assert!(bypasses(no_proxy, "10.0.0.1"));
+        assert!(!bypasses(no_proxy, "example.com"));
+        assert!(!bypasses(no_proxy, "notinternal.example.com"));
+        assert!(!bypasses(",,", "localhost"));
+        assert!(bypasses("*", "feed.example.com"));
+    }
+
+    #[test]
+    fn proxy_for_picks_the_variable_by_scheme() {
+        let vars = [
+            ("HTTPS_PROXY", "http://secure.proxy:3128"),
+            ("HTTP_PROXY", "http://plain.proxy:3128"),
+        ];
+        assert_eq!(
+            proxy("wss://feed.example.com", &vars).as_deref(),
+            Some("http://secure.proxy:3128/")
+        );
+        assert_eq!(
+            proxy("ws://feed.example.com", &vars).as_deref(),
+            Some("http://plain.proxy:3128/")
+        );
+    }
+
+    #[test]
+    fn proxy_for_falls_back_to_ws_proxy() {
+        let vars = [
+            ("HTTP_PROXY", "http://plain.proxy:3128"),
+            ("WS_PROXY", "socks5://ws.proxy:1080"),
+        ];
+        assert_eq!(
+            proxy("wss://feed.example.com", &vars).as_deref(),
+            Some("socks5://ws.proxy:1080")
+        );
+        assert_eq!(proxy("wss://feed.example.com", &[]), None);
+    }
+
+    #[test]
+    fn proxy_for_honours_no_proxy() {
+        let vars = [
+            ("HTTPS_PROXY", "http://secure.proxy:3128"),
+            ("NO_PROXY", "example.com"),
+        ];
+        assert_eq!(proxy("wss://feed.example.com", &vars), None);
+        assert!(proxy("wss://feed.example.org", &vars).is_some());
+    }
+
+    #[test]
+    fn proxy_for_defaults_to_http_and_ignores_other_schemes() {
+        assert_eq!(
+            proxy("wss://feed.example.com", &[("HTTPS_PROXY", "proxy:3128")]).as_deref(),
+            Some("http://proxy:3128/")
+        );
+        assert_eq!(
+            proxy(
+                "wss://feed.example.com",
+                &[("HTTPS_PROXY", "https://proxy:3128")]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn env_var_prefers_the_lowercase_name_and_skips_blank_values() {
+        std::env::set_var("WEBSOCKET_PROVIDER_TEST_PROXY", "upper");
+        std::env::set_var("websocket_provider_test_proxy", " lower ");
+        assert_eq!(
+            env_var("WEBSOCKET_PROVIDER_TEST_PROXY").as_deref(),
+            Some("lower")
+        );
+        std::env::set_var("websocket_provider_test_proxy", "  ");
+        assert_eq!(env_var("WEBSOCKET_PROVIDER_TEST_PROXY"), None);
+        std::env::remove_var("websocket_provider_test_proxy");
+        std::env::remove_var("WEBSOCKET_PROVIDER_TEST_PROXY");
+    }
+
+    /// Accept one connection, answering each expected request with its reply,
+    /// and return the URL of a `scheme` proxy on that port
+    async fn mock_proxy(scheme: &str, exchange: Vec<(Vec<u8>, Vec<u8>)>) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            for (expected, reply) in exchange {
+                let mut received = vec![0u8; expected.len()];
+                stream.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, expected);
+                stream.write_all(&reply).await.unwrap();
+            }
+        });
+        Url::parse(&format!("{}127.0.0.1:{}", scheme, port)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn http_connect_opens_a_tunnel() {
+        let request =
+            b"CONNECT feed.example.com:443 HTTP/1.1\r\nHost: feed.example.com:443\r\n\r\n";
+        let proxy_url = mock_proxy(
+            "http://",
+            vec![(
+                request.to_vec(),
+                b"HTTP/1.1 200 Connection established\r\n\r\nhello".to_vec(),
+            )],
+        )
+        .await;
+
+        let mut stream = connect_via(&proxy_url, "feed.example.com", 443)
+            .await
+            .unwrap();
+        // Bytes after the response head belong to the tunnel
+        let mut tunnelled = [0u8; 5];
+        stream.read_exact(&mut tunnelled).await.unwrap();
+        assert_eq!(&tunnelled, b"hello");
+    }
+
+    #[tokio::test]
+    async fn http_connect_reports_a_refusal() {
+        let request =
+            b"CONNECT feed.example.com:443 HTTP/1.1\r\nHost: feed.example.com:443\r\n\r\n";
+        let proxy_url = mock_proxy(
+            "http://",
+            vec![(
+                request.to_vec(),
+                b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n".to_vec(),
+            )],
+        )
+        .await;
+
+        let err = connect_via(&proxy_url, "feed.example.com", 443)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProviderError>(),
+            Some(ProviderError::ProxyRefused { reason, .. }) if reason.contains("407")
+        ));
+    }
+
+    #[tokio::test]
+    async fn socks5_connect_authenticates_and_sends_the_host_name() {
+        let mut connect = vec![5, 1, 0, 3, 16];
+        connect.extend_from_slice(b"feed.example.com");
+        connect.extend_from_slice(&443u16.to_be_bytes());
+        let proxy_url = mock_proxy(
+            "socks5://user:secret@",
+            vec![
+                (vec![5, 2, 0, 2], vec![5, 2]),
+                (b"\x01\x04user\x06secret".to_vec(), vec![1, 0]),
+                (connect, vec![5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90]),
+            ],
+        )
+        .await;
+
+        connect_via(&proxy_url, "feed.example.com", 443)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn socks5_connect_reports_the_reply_code() {
+        let mut connect = vec![5, 1, 0, 1, 10, 0, 0, 1];
+        connect.extend_from_slice(&443u16.to_be_bytes());
+        let proxy_url = mock_proxy(
+            "socks5://",
+            vec![
+                (vec![5, 1, 0], vec![5, 0]),
+                (connect, vec![5, 5, 0, 1, 0, 0, 0, 0, 0, 0]),
+            ],
+        )
+        .await;
+
+        let err = connect_via(&proxy_url, "10.0.0.1", 443).await.unwrap_err();
+        assert!(err.to_string().contains("connection refused"));
+    }
+}
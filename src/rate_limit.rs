@@ -0,0 +1,81 @@
+//! Token bucket limiting how fast data messages are forwarded
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Refills `rate` tokens per second, holding at most one second's worth
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Create a full bucket allowing `per_second` messages per second
+    pub fn new(per_second: u32) -> Self {
+        let rate = f64::from(per_second.max(1));
+        Self {
+            rate,
+            tokens: rate,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Take a token, or return how long until one is available
+    pub fn try_acquire(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_with_one_second_of_tokens() {
+        let mut bucket = TokenBucket::new(5);
+        for _ in 0..5 {
+            bucket.try_acquire().unwrap();
+        }
+        let wait = bucket.try_acquire().unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let mut bucket = TokenBucket::new(100);
+        while bucket.try_acquire().is_ok() {}
+        std::thread::sleep(Duration::from_millis(50));
+        bucket.try_acquire().unwrap();
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_rate() {
+        let mut bucket = TokenBucket::new(50);
+        // Idle time on a full bucket must not bank extra tokens
+        std::thread::sleep(Duration::from_millis(100));
+        for _ in 0..50 {
+            bucket.try_acquire().unwrap();
+        }
+        assert!(bucket.try_acquire().is_err());
+    }
+
+    #[test]
+    fn zero_rate_allows_one_per_second() {
+        let mut bucket = TokenBucket::new(0);
+        bucket.try_acquire().unwrap();
+        assert!(bucket.try_acquire().unwrap_err() <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,112 @@
+//! Bounded history of connection events for postmortem analysis
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::message::MessageType;
+
+/// Maximum number of events kept per connection
+const MAX_EVENTS: usize = 1000;
+
+/// A single connection event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TimelineEvent {
+    /// Connection attempt started
+    Connecting,
+    /// WebSocket handshake completed
+    Connected,
+    /// A data message was received
+    MessageReceived {
+        message_type: MessageType,
+        size: usize,
+    },
+    /// Server sent a close frame
+    Closed { reason: String },
+    /// Connection ended (normally or with an error)
+    Disconnected,
+    /// Connection or receive error
+    Error { message: String },
+    /// Waiting before the next reconnection attempt
+    Reconnecting { attempt: u32, delay_ms: u64 },
+}
+
+/// Ring buffer of the most recent connection events
+#[derive(Debug, Default)]
+pub struct Timeline {
+    events: VecDeque<(Instant, TimelineEvent)>,
+}
+
+impl Timeline {
+    /// Record an event, evicting the oldest once full
+    pub fn record(&mut self, event: TimelineEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back((Instant::now(), event));
+    }
+
+    /// Snapshot of the recorded events, oldest first
+    pub fn events(&self) -> Vec<(Instant, TimelineEvent)> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+/// Serialize events as JSON, with each event's age in milliseconds
+pub fn to_json(events: &[(Instant, TimelineEvent)]) -> serde_json::Value {
+    let now = Instant::now();
+    events
+        .iter()
+        .map(|(at, event)| {
+            let mut value = serde_json::to_value(event).unwrap_or_default();
+            if let Some(object) = value.as_object_mut() {
+                object.insert(
+                    "ago_ms".to_string(),
+                    (now.duration_since(*at).as_millis() as u64).into(),
+                );
+            }
+            value
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_keeps_the_most_recent_events() {
+        let mut timeline = Timeline::default();
+        for attempt in 0..MAX_EVENTS as u32 + 5 {
+            timeline.record(TimelineEvent::Reconnecting {
+                attempt,
+                delay_ms: 0,
+            });
+        }
+        let events = timeline.events();
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(
+            events[0].1,
+            TimelineEvent::Reconnecting {
+                attempt: 5,
+                delay_ms: 0
+            }
+        );
+    }
+
+    #[test]
+    fn to_json_tags_events_with_their_age() {
+        let mut timeline = Timeline::default();
+        timeline.record(TimelineEvent::Connected);
+        timeline.record(TimelineEvent::Closed {
+            reason: "bye".to_string(),
+        });
+        let json = to_json(&timeline.events());
+        assert_eq!(json[0]["event"], "connected");
+        assert_eq!(json[1]["event"], "closed");
+        assert_eq!(json[1]["reason"], "bye");
+        assert!(json[1]["ago_ms"].is_u64());
+    }
+}
@@ -0,0 +1,111 @@
+//! Shorthand for the most common pipeline stages
+//!
+//! A link can set `transform` to a JSON object instead of spelling out the
+//! equivalent `pipeline` stages:
+//!
+//! ```json
+//! {"strip_outer_array": true, "add_headers": {"source": "feed"}, "max_payload_bytes": 4096}
+//! ```
+//!
+//! The object expands to `unpack_array`, one `add_field` per header, then
+//! `truncate`, and those stages run after any configured `pipeline`.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::pipeline::Stage;
+
+/// Parsed `transform` link config
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransformConfig {
+    /// Top-level fields set on every JSON object payload
+    pub add_headers: HashMap<String, String>,
+    /// Forward each element of a top-level JSON array as its own message
+    pub strip_outer_array: bool,
+    /// Cut payloads down to this many bytes (0 for unlimited)
+    pub max_payload_bytes: usize,
+}
+
+impl TransformConfig {
+    /// Parse a transform from its JSON config representation
+    pub fn parse(json: &str) -> anyhow::Result<Self> {
+        serde_json::from_str(json).map_err(|e| anyhow::anyhow!("Invalid transform: {}", e))
+    }
+
+    /// The pipeline stages this transform stands for
+    pub fn stages(&self) -> Vec<Stage> {
+        let mut stages = Vec::new();
+        if self.strip_outer_array {
+            stages.push(Stage::UnpackArray);
+        }
+        // Sorted so the expanded pipeline does not depend on map order
+        let mut headers: Vec<_> = self.add_headers.iter().collect();
+        headers.sort();
+        stages.extend(headers.into_iter().map(|(field, value)| Stage::AddField {
+            field: field.clone(),
+            value: Value::from(value.as_str()),
+        }));
+        if self.max_payload_bytes > 0 {
+            stages.push(Stage::Truncate {
+                max_bytes: self.max_payload_bytes,
+            });
+        }
+        stages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::WebSocketMessage;
+    use crate::pipeline;
+
+    fn run(json: &str, payload: &str) -> Vec<String> {
+        let stages = TransformConfig::parse(json).unwrap().stages();
+        pipeline::run(&stages, WebSocketMessage::text(payload))
+            .unwrap()
+            .iter()
+            .map(|message| String::from_utf8(message.payload.clone()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn add_headers_sets_fields_on_json_objects() {
+        let out = run(r#"{"add_headers": {"source": "feed"}}"#, r#"{"id": 1}"#);
+        assert_eq!(out, [r#"{"id":1,"source":"feed"}"#]);
+        assert_eq!(
+            run(r#"{"add_headers": {"source": "feed"}}"#, "plain"),
+            ["plain"]
+        );
+    }
+
+    #[test]
+    fn strip_outer_array_unpacks_before_adding_headers() {
+        let out = run(
+            r#"{"strip_outer_array": true, "add_headers": {"source": "feed"}}"#,
+            r#"[{"id": 1}, {"id": 2}]"#,
+        );
+        assert_eq!(
+            out,
+            [r#"{"id":1,"source":"feed"}"#, r#"{"id":2,"source":"feed"}"#]
+        );
+    }
+
+    #[test]
+    fn max_payload_bytes_truncates_last() {
+        let out = run(
+            r#"{"add_headers": {"source": "feed"}, "max_payload_bytes": 8}"#,
+            r#"{"id": 1}"#,
+        );
+        assert_eq!(out, [r#"{"id":1,"#]);
+        assert_eq!(TransformConfig::parse("{}").unwrap().stages(), []);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(TransformConfig::parse(r#"{"strip_array": true}"#).is_err());
+    }
+}
@@ -1,50 +1,839 @@
-use crate::config::LinkConfig;
-use futures_util::StreamExt;
-use tokio::time::sleep;
-use tokio_tungstenite::{connect_async_tls_with_config, tungstenite::Message, Connector};
-use tracing::{debug, error, info, warn};
-
-/// Build a rustls Connector with webpki root certificates for wss:// connections
-fn build_tls_connector() -> Connector {
-    let root_store =
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::config::{LinkConfig, ProtocolViolationPolicy, RateLimitPolicy, TlsVersion};
+use crate::error::{ProviderError, ProviderResult};
+use crate::message::{ConnectionMetadata, MessageType, WebSocketMessage};
+use crate::metrics::{ClientStats, MetricsSnapshot};
+use crate::pipeline;
+use crate::proxy;
+use crate::rate_limit::TokenBucket;
+use crate::timeline::{Timeline, TimelineEvent};
+use anyhow::Context as _;
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval_at, sleep_until, timeout, Instant, Interval, MissedTickBehavior};
+use tokio_tungstenite::{
+    client_async_tls_with_config, tungstenite::Message, Connector, MaybeTlsStream, WebSocketStream,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::error::ProtocolError;
+use tungstenite::handshake::client::Request;
+use tungstenite::http::header::{
+    AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL, SET_COOKIE,
+};
+use tungstenite::http::{HeaderMap, HeaderName, HeaderValue};
+use url::Url;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsSink = SplitSink<WsStream, Message>;
+
+/// Outbound frames queued per client before sends are rejected
+const OUTBOUND_QUEUE_SIZE: usize = 256;
+
+/// What `max_messages_per_sec` does with a data frame
+enum Throttle {
+    /// A token was available
+    Forward,
+    /// Over the rate under the `drop` policy
+    Drop,
+    /// Over the rate under the `delay` policy: hold the frame until the deadline
+    Delay(Instant),
+}
+
+/// Sleep until an optional deadline, or forever if unset
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the next tick of an optional interval, or forever if unset
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Randomly move `delay` up or down by up to `pct` percent
+///
+/// Keeps clients that lost the same server at the same moment from
+/// reconnecting in lockstep.
+fn jittered(delay: Duration, pct: u8) -> Duration {
+    if pct == 0 {
+        return delay;
+    }
+    let spread = f64::from(pct.min(100)) / 100.0;
+    delay.mul_f64(rand::thread_rng().gen_range(1.0 - spread..=1.0 + spread))
+}
+
+/// Uniformly random delay in `0..max`
+fn random_delay(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    rand::thread_rng().gen_range(Duration::ZERO..max)
+}
+
+/// Jitter the backoff, then spread a fleet's reconnects further
+fn reconnect_delay(backoff: Duration, jitter_pct: u8, spread: Duration) -> Duration {
+    jittered(backoff, jitter_pct) + random_delay(spread)
+}
+
+/// Build the opening handshake request for `url` with the link's extra headers,
+/// subprotocols and bearer token
+pub(crate) fn handshake_request(config: &LinkConfig, url: &Url) -> anyhow::Result<Request> {
+    let mut request = url.as_str().into_client_request()?;
+    for (name, value) in &config.headers {
+        request.headers_mut().insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    if !config.subprotocols.is_empty() {
+        request.headers_mut().insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_str(&config.subprotocols.join(", "))?,
+        );
+    }
+    if let Some(token) = &config.auth_token {
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token))?,
+        );
+    }
+    Ok(request)
+}
+
+/// Headers whose values carry credentials and must never be logged
+const SENSITIVE_HEADERS: [HeaderName; 4] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE];
+
+/// Header names and values for logging, with credential values redacted
+fn redacted_headers(headers: &HeaderMap) -> Vec<(&str, &str)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(name) {
+                "<redacted>"
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            (name.as_str(), value)
+        })
+        .collect()
+}
+
+/// Build a rustls client config with webpki root certificates for wss:// connections
+///
+/// Only the TLS versions allowed by the link's `tls_min_version`/`tls_max_version`
+/// are offered, so servers that can't meet the constraint fail the handshake.
+/// Certificates from `ca_cert_path` are trusted alongside the WebPKI roots, and
+/// a configured client certificate is presented for mutual TLS.
+pub(crate) fn tls_client_config(config: &LinkConfig) -> anyhow::Result<Arc<rustls::ClientConfig>> {
+    let versions: Vec<&'static rustls::SupportedProtocolVersion> = [
+        (TlsVersion::Tls12, &rustls::version::TLS12),
+        (TlsVersion::Tls13, &rustls::version::TLS13),
+    ]
+    .into_iter()
+    .filter(|(version, _)| config.allows_tls_version(*version))
+    .map(|(_, supported)| supported)
+    .collect();
+
+    let mut root_store =
         rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(ca_path) = &config.ca_cert_path {
+        for cert in CertificateDer::pem_file_iter(ca_path)
+            .with_context(|| format!("Failed to read CA certificate {}", ca_path))?
+        {
+            let cert =
+                cert.with_context(|| format!("Failed to read CA certificate {}", ca_path))?;
+            root_store
+                .add(cert)
+                .with_context(|| format!("Invalid CA certificate in {}", ca_path))?;
+        }
+    }
     let tls_config = rustls::ClientConfig::builder_with_provider(
         rustls::crypto::ring::default_provider().into(),
     )
-    .with_safe_default_protocol_versions()
-    .expect("failed to set TLS protocol versions")
-    .with_root_certificates(root_store)
-    .with_no_client_auth();
-    Connector::Rustls(std::sync::Arc::new(tls_config))
+    .with_protocol_versions(&versions)
+    .map_err(|e| anyhow::anyhow!("Invalid TLS version constraint: {}", e))?
+    .with_root_certificates(root_store);
+
+    let tls_config = match (&config.client_cert_path, &config.client_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = CertificateDer::pem_file_iter(cert_path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .with_context(|| format!("Failed to read client certificate {}", cert_path))?;
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .with_context(|| format!("Failed to read client key {}", key_path))?;
+            tls_config
+                .with_client_auth_cert(certs, key)
+                .context("Invalid client certificate or key")?
+        }
+        _ => tls_config.with_no_client_auth(),
+    };
+    Ok(Arc::new(tls_config))
+}
+
+/// Build a rustls Connector for wss:// connections
+fn build_tls_connector(config: &LinkConfig) -> anyhow::Result<Connector> {
+    Ok(Connector::Rustls(tls_client_config(config)?))
+}
+
+/// URLs a connection to `url` tries, in order
+///
+/// With `auto_upgrade_tls`, a ws:// URL is tried as wss:// first and then
+/// in plaintext. `require_tls` drops the plaintext URL, so a ws:// URL
+/// without `auto_upgrade_tls` has nothing left to try.
+pub(crate) fn connection_urls(config: &LinkConfig, url: &Url) -> anyhow::Result<Vec<Url>> {
+    if url.scheme() != "ws" {
+        return Ok(vec![url.clone()]);
+    }
+    let mut urls = Vec::with_capacity(2);
+    if config.auto_upgrade_tls {
+        let mut tls_url = url.clone();
+        tls_url
+            .set_scheme("wss")
+            .map_err(|_| anyhow::anyhow!("Cannot upgrade {} to wss://", url))?;
+        urls.push(tls_url);
+    }
+    if !config.require_tls {
+        urls.push(url.clone());
+    }
+    Ok(urls)
+}
+
+/// Error for a URL that `require_tls` leaves nothing to connect to
+pub(crate) fn require_tls_error(url: &Url) -> anyhow::Error {
+    ProviderError::ConfigError(format!(
+        "require_tls forbids a plaintext connection to {}",
+        url
+    ))
+    .into()
+}
+
+/// Open a TCP stream to `url`'s host, through the link's proxy if any
+///
+/// Fails with a `tcp_connect` timeout after `connect_timeout_secs`.
+pub(crate) async fn connect_tcp(config: &LinkConfig, url: &Url) -> anyhow::Result<TcpStream> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("WebSocket URL has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let proxy = config.proxy(url);
+    let connect_timeout = config.connect_timeout();
+    timeout(connect_timeout, async {
+        match &proxy {
+            Some(proxy) => {
+                debug!("Tunnelling through {} proxy", proxy.scheme());
+                proxy::connect_via(proxy, host, port).await
+            }
+            None => Ok(TcpStream::connect((host, port)).await?),
+        }
+    })
+    .await
+    .map_err(|_| ProviderError::Timeout {
+        operation: "tcp_connect".into(),
+        timeout: connect_timeout,
+    })?
+}
+
+/// Error for a TLS handshake and WebSocket upgrade that overran `handshake_timeout_ms`
+pub(crate) fn handshake_timeout_error(handshake_timeout: Duration) -> ProviderError {
+    ProviderError::Timeout {
+        operation: "websocket_handshake".into(),
+        timeout: handshake_timeout,
+    }
+}
+
+/// Build the end-of-stream marker forwarded when the client stops
+///
+/// The marker is a small JSON object so consumers can tell it apart from
+/// regular payloads arriving on the same subject.
+fn end_of_stream_message(reason: &str) -> String {
+    serde_json::json!({
+        "websocket_eos": true,
+        "reason": reason,
+    })
+    .to_string()
+}
+
+/// Build the marker forwarded when a connection is lost and a reconnect begins
+fn gap_message(reason: &str) -> String {
+    serde_json::json!({
+        "websocket_gap": true,
+        "reason": reason,
+        "timestamp": unix_timestamp(),
+    })
+    .to_string()
+}
+
+/// Build the marker forwarded once the connection is back, with the outage length
+fn resume_message(outage: Duration) -> String {
+    serde_json::json!({
+        "websocket_resume": true,
+        "outage_ms": outage.as_millis() as u64,
+        "timestamp": unix_timestamp(),
+    })
+    .to_string()
+}
+
+/// Build a periodic heartbeat reporting whether the connection is up
+fn heartbeat_message(
+    source_id: Option<&str>,
+    websocket_url: &str,
+    connected: bool,
+    messages_received: u64,
+) -> String {
+    serde_json::json!({
+        "status": if connected { "connected" } else { "disconnected" },
+        "connected": connected,
+        "source_id": source_id,
+        "url": websocket_url,
+        "messages_received": messages_received,
+        "timestamp": unix_timestamp(),
+    })
+    .to_string()
+}
+
+/// Build a lifecycle event for `status_subject`
+fn status_message(event: &str, websocket_url: &str, attempt: Option<u32>) -> String {
+    let mut json = serde_json::json!({
+        "event": event,
+        "url": websocket_url,
+        "timestamp": unix_timestamp(),
+    });
+    if let Some(attempt) = attempt {
+        json["attempt"] = attempt.into();
+    }
+    json.to_string()
+}
+
+/// Seconds since the Unix epoch
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Connect once and collect up to `count` messages, or as many as arrive before `limit`
+///
+/// Intended for scripts and tests: there is no reconnection, and the
+/// connection is closed gracefully before returning. A server-initiated close
+/// ends collection early and returns what was received so far.
+pub async fn receive_messages(
+    config: LinkConfig,
+    count: usize,
+    limit: Duration,
+) -> anyhow::Result<Vec<WebSocketMessage>> {
+    let client = WebSocketClient::new(config);
+    let (mut write, mut read) = client.connect().await?.split();
+    client.send_subscription(&mut write).await?;
+
+    let mut messages = Vec::with_capacity(count);
+    let collect = async {
+        while messages.len() < count {
+            let Some(message) = read.next().await else {
+                break;
+            };
+            let message = message?;
+            if message.is_close() {
+                break;
+            }
+            client.handle_message(message, &mut |message| {
+                messages.push(message);
+                Ok(())
+            })?;
+        }
+        anyhow::Ok(())
+    };
+    match timeout(limit, collect).await {
+        Ok(result) => result?,
+        Err(_) => debug!("Stopped collecting after {:?}", limit),
+    }
+
+    if let Err(e) = write.close().await {
+        debug!("Failed to close WebSocket cleanly: {}", e);
+    }
+    messages.truncate(count);
+    Ok(messages)
+}
+
+/// Where a client is in its connection lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    /// Opening a connection (TCP, TLS and WebSocket upgrade)
+    Connecting,
+    /// Connected and receiving messages
+    Connected,
+    /// Waiting before reconnection attempt `attempt`
+    Reconnecting { attempt: u32 },
+    /// The client stopped after the connection ended normally or was drained
+    Closed,
+    /// The client gave up after an error
+    Failed,
 }
 
 /// WebSocket client handler
 pub struct WebSocketClient {
     config: LinkConfig,
+    source_id: Option<String>,
+    url_index: Mutex<usize>,
+    status: Mutex<ConnectionStatus>,
+    timeline: Arc<Mutex<Timeline>>,
+    stats: Mutex<ClientStats>,
+    drain: CancellationToken,
+    session: Mutex<Session>,
+    batch: Mutex<Batch>,
+    reply_waiter: Mutex<Option<oneshot::Sender<WebSocketMessage>>>,
+    request_lock: tokio::sync::Mutex<()>,
+    outbound_tx: mpsc::Sender<Message>,
+    outbound_rx: tokio::sync::Mutex<mpsc::Receiver<Message>>,
+}
+
+/// Connection lifecycle callbacks passed to [`WebSocketClient::run_with_hooks`]
+struct Hooks<'a> {
+    on_connect: &'a (dyn Fn() + Send + Sync),
+    on_disconnect: &'a (dyn Fn(&anyhow::Error) + Send + Sync),
+}
+
+impl Hooks<'_> {
+    /// No callbacks, for the plain `run` and `connect_once`
+    const NONE: Hooks<'static> = Hooks {
+        on_connect: &|| {},
+        on_disconnect: &|_| {},
+    };
+}
+
+/// Data messages held back until `batch_size` or `batch_timeout_ms` is reached
+#[derive(Debug, Default)]
+struct Batch {
+    messages: Vec<WebSocketMessage>,
+    deadline: Option<Instant>,
+}
+
+/// The current connection, as reported in message metadata
+#[derive(Debug, Default)]
+struct Session {
+    subprotocol: Option<String>,
+    server_ip: Option<String>,
+    epoch: u64,
+    connected_at: Option<Instant>,
+    sequence: u64,
 }
 
 impl WebSocketClient {
     /// Create a new WebSocket client
     pub fn new(config: LinkConfig) -> Self {
-        Self { config }
+        let (outbound_tx, outbound_rx) = mpsc::channel(OUTBOUND_QUEUE_SIZE);
+        Self {
+            config,
+            source_id: None,
+            url_index: Mutex::default(),
+            status: Mutex::new(ConnectionStatus::Connecting),
+            timeline: Arc::default(),
+            stats: Mutex::default(),
+            drain: CancellationToken::new(),
+            session: Mutex::default(),
+            batch: Mutex::default(),
+            reply_waiter: Mutex::default(),
+            request_lock: tokio::sync::Mutex::default(),
+            outbound_tx,
+            outbound_rx: tokio::sync::Mutex::new(outbound_rx),
+        }
+    }
+
+    /// Name the component this client forwards to, for reporting in heartbeats
+    pub fn with_source_id(mut self, source_id: impl Into<String>) -> Self {
+        self.source_id = Some(source_id.into());
+        self
+    }
+
+    /// URL the client is currently connecting or connected to
+    pub fn current_url(&self) -> String {
+        let index = *self.lock_url_index();
+        self.config
+            .urls()
+            .nth(index)
+            .unwrap_or(&self.config.websocket_url)
+            .to_string()
+    }
+
+    /// Fail over to the next configured URL, wrapping back to `websocket_url`
+    pub fn next_url(&self) -> String {
+        let urls = self.config.websocket_urls.len() + 1;
+        {
+            let mut index = self.lock_url_index();
+            *index = (*index + 1) % urls;
+        }
+        self.current_url()
+    }
+
+    fn lock_url_index(&self) -> std::sync::MutexGuard<'_, usize> {
+        self.url_index
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Link configuration the client was created with
+    pub fn config(&self) -> &LinkConfig {
+        &self.config
+    }
+
+    /// Current connection status
+    pub fn status(&self) -> ConnectionStatus {
+        *self
+            .status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn set_status(&self, status: ConnectionStatus) {
+        *self
+            .status
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = status;
+    }
+
+    /// Recent connection events, oldest first
+    pub fn timeline(&self) -> Vec<(std::time::Instant, TimelineEvent)> {
+        self.lock_timeline().events()
+    }
+
+    fn lock_timeline(&self) -> std::sync::MutexGuard<'_, Timeline> {
+        // A poisoned timeline is still useful for diagnostics
+        self.timeline
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&self, event: TimelineEvent) {
+        self.lock_timeline().record(event);
+    }
+
+    /// Total data messages received and the current rate in messages per second
+    pub fn message_stats(&self) -> (u64, f64) {
+        let mut stats = self.lock_stats();
+        (stats.messages_received(), stats.current_rate_per_second())
+    }
+
+    /// Queue a frame to send to the server
+    ///
+    /// Frames are written by the receive loop, so ones queued while the
+    /// client is reconnecting go out once the connection is back.
+    pub fn send_message(&self, data: Vec<u8>, is_binary: bool) -> ProviderResult<()> {
+        let message = if is_binary {
+            Message::Binary(data)
+        } else {
+            Message::Text(String::from_utf8(data)?)
+        };
+        self.outbound_tx.try_send(message).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => ProviderError::OutboundQueueFull,
+            mpsc::error::TrySendError::Closed(_) => ProviderError::ConnectionClosed,
+        })
+    }
+
+    /// Send a frame and take the next data message from the server as its reply
+    ///
+    /// The reply is returned here instead of being forwarded. Requests are
+    /// served one at a time, so concurrent callers wait their turn; the
+    /// timeout covers only this request's own wait for a reply.
+    pub async fn request(
+        &self,
+        data: Vec<u8>,
+        is_binary: bool,
+        reply_timeout: Duration,
+    ) -> ProviderResult<WebSocketMessage> {
+        let _turn = self.request_lock.lock().await;
+        let (tx, rx) = oneshot::channel();
+        // Register before sending so a fast reply cannot slip past
+        *self.lock_reply_waiter() = Some(tx);
+        if let Err(e) = self.send_message(data, is_binary) {
+            self.lock_reply_waiter().take();
+            return Err(e);
+        }
+        match timeout(reply_timeout, rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(ProviderError::ConnectionClosed),
+            Err(_) => {
+                self.lock_reply_waiter().take();
+                Err(ProviderError::Timeout {
+                    operation: "WebSocket reply".to_string(),
+                    timeout: reply_timeout,
+                })
+            }
+        }
+    }
+
+    fn lock_reply_waiter(
+        &self,
+    ) -> std::sync::MutexGuard<'_, Option<oneshot::Sender<WebSocketMessage>>> {
+        self.reply_waiter
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Stop forwarding new frames and close the connection gracefully
+    ///
+    /// The client does not reconnect once draining has started.
+    pub fn drain(&self) {
+        self.drain.cancel();
+    }
+
+    /// Wait until draining has started
+    pub async fn drain_started(&self) {
+        self.drain.cancelled().await;
+    }
+
+    /// Messages dropped for exceeding `max_message_size` since the client started
+    pub fn oversized_messages_total(&self) -> u64 {
+        self.lock_stats().oversized_messages_total()
+    }
+
+    /// Protocol violations by the server since the client started
+    pub fn protocol_violations_total(&self) -> u64 {
+        self.lock_stats().protocol_violations_total()
+    }
+
+    fn lock_session(&self) -> std::sync::MutexGuard<'_, Session> {
+        self.session
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Metadata for the next forwarded message, advancing the sequence number
+    fn connection_metadata(&self) -> ConnectionMetadata {
+        let mut session = self.lock_session();
+        session.sequence += 1;
+        ConnectionMetadata {
+            subprotocol: session.subprotocol.clone(),
+            server_ip: session.server_ip.clone(),
+            epoch: session.epoch,
+            uptime_ms: session
+                .connected_at
+                .map(|at| at.elapsed().as_millis() as u64)
+                .unwrap_or_default(),
+            source_url: self.current_url(),
+            sequence: session.sequence,
+        }
+    }
+
+    /// Snapshot of the delivery counters
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.lock_stats().snapshot()
+    }
+
+    /// Count a message delivered to the component
+    pub fn record_forwarded(&self) {
+        self.lock_stats().record_forwarded();
+    }
+
+    /// Count a message dropped after leaving the client
+    pub fn record_dropped(&self) {
+        self.lock_stats().record_dropped();
+    }
+
+    fn lock_stats(&self) -> std::sync::MutexGuard<'_, ClientStats> {
+        self.stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Count a received data message in the timeline and stats
+    fn record_received(&self, message_type: MessageType, size: usize) {
+        self.record(TimelineEvent::MessageReceived { message_type, size });
+        self.lock_stats().record_message();
+        #[cfg(feature = "prometheus")]
+        crate::prometheus::record_message(size);
     }
 
     /// Connect to the WebSocket server and start receiving messages
-    pub async fn run<F>(&self, mut message_handler: F) -> anyhow::Result<()>
+    pub async fn run<F>(&self, message_handler: F) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()> + Send,
+    {
+        self.run_inner(message_handler, &Hooks::NONE).await
+    }
+
+    /// Like [`run`](Self::run), calling `on_connect` each time a connection is
+    /// established and `on_disconnect` with the error each time one is lost
+    ///
+    /// Failed connection attempts call neither hook; an established
+    /// connection that ends cleanly (a drain) does not call `on_disconnect`.
+    pub async fn run_with_hooks<F, C, D>(
+        &self,
+        message_handler: F,
+        on_connect: C,
+        on_disconnect: D,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()> + Send,
+        C: Fn() + Send + Sync,
+        D: Fn(&anyhow::Error) + Send + Sync,
+    {
+        let hooks = Hooks {
+            on_connect: &on_connect,
+            on_disconnect: &on_disconnect,
+        };
+        self.run_inner(message_handler, &hooks).await
+    }
+
+    async fn run_inner<F>(&self, mut message_handler: F, hooks: &Hooks<'_>) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()> + Send,
+    {
+        // Every log line of the session carries its URL, component and attempt
+        let span = info_span!(
+            "websocket_session",
+            url = %self.current_url(),
+            source_id = self.source_id.as_deref().unwrap_or_default(),
+            attempt = 0u32,
+        );
+        let result = self
+            .reconnect_loop(&mut message_handler, hooks)
+            .instrument(span)
+            .await;
+        self.emit_end_of_stream(&mut message_handler, &result);
+        self.set_status(match result {
+            Ok(_) => ConnectionStatus::Closed,
+            Err(_) => ConnectionStatus::Failed,
+        });
+        result
+    }
+
+    /// Connect and receive messages once, without reconnecting
+    ///
+    /// Returns when the connection ends: `Ok` after a drain or when the
+    /// stream ends, and the error otherwise, including a close frame from the
+    /// server. Useful for one-shot scrapes and tests; [`run`](Self::run) adds
+    /// the reconnect loop around the same connection logic.
+    pub async fn connect_once<F>(&self, mut message_handler: F) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()> + Send,
+    {
+        let mut heartbeat = self.heartbeat_interval();
+        let result = self
+            .connect_and_receive(
+                &mut message_handler,
+                &mut None,
+                &mut heartbeat,
+                &Hooks::NONE,
+            )
+            .await;
+        self.emit_end_of_stream(&mut message_handler, &result);
+        self.set_status(match result {
+            Ok(_) => ConnectionStatus::Closed,
+            Err(_) => ConnectionStatus::Failed,
+        });
+        result
+    }
+
+    /// Ticks for `heartbeat_subject`, if heartbeats are enabled
+    fn heartbeat_interval(&self) -> Option<Interval> {
+        self.config.heartbeat().map(|(_, period)| {
+            let mut heartbeat = interval_at(Instant::now() + period, period);
+            heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            heartbeat
+        })
+    }
+
+    /// Connect, receive and reconnect until the client stops
+    async fn reconnect_loop<F>(
+        &self,
+        mut message_handler: F,
+        hooks: &Hooks<'_>,
+    ) -> anyhow::Result<()>
     where
-        F: FnMut(Vec<u8>) -> anyhow::Result<()> + Send,
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()> + Send,
     {
         let mut reconnect_attempts = 0u32;
+        // Consecutive failed attempts against the URL currently in use
+        let mut url_failures = 0u32;
         let mut current_delay = self.config.initial_reconnect_delay();
+        // Start of the current outage, while reconnecting after a lost connection
+        let mut outage_started = None;
+        let mut breaker = CircuitBreaker::new(
+            self.config.circuit_open_threshold,
+            self.config.circuit_reset(),
+        );
+        // Heartbeats keep ticking across reconnects, reporting the backoff as disconnected
+        let mut heartbeat = self.heartbeat_interval();
 
         loop {
-            match self.connect_and_receive(&mut message_handler).await {
+            if self.drain.is_cancelled() {
+                info!("WebSocket client drained");
+                break Ok(());
+            }
+
+            let epoch = self.lock_session().epoch;
+            let result = self
+                .connect_and_receive(
+                    &mut message_handler,
+                    &mut outage_started,
+                    &mut heartbeat,
+                    hooks,
+                )
+                .await;
+
+            // A connection that was established before failing starts a fresh
+            // backoff cycle, so only consecutive failed attempts grow the delay
+            let established = self.lock_session().epoch != epoch;
+            if established {
+                reconnect_attempts = 0;
+                url_failures = 0;
+                Span::current().record("attempt", reconnect_attempts);
+                current_delay = self.config.initial_reconnect_delay();
+                breaker.record_success();
+
+                // Reconnects start over from the primary URL, not the fallback
+                // that was last in use
+                if std::mem::take(&mut *self.lock_url_index()) != 0 {
+                    info!("Returning to primary URL {}", self.config.websocket_url);
+                    Span::current().record("url", self.config.websocket_url.as_str());
+                }
+            }
+
+            match result {
                 Ok(_) => {
                     info!("WebSocket connection closed normally");
                     break Ok(());
                 }
                 Err(e) => {
                     error!("WebSocket connection error: {}", e);
+                    self.record(TimelineEvent::Error {
+                        message: e.to_string(),
+                    });
+
+                    if self.config.on_protocol_violation == ProtocolViolationPolicy::Fail
+                        && matches!(
+                            e.downcast_ref::<ProviderError>(),
+                            Some(ProviderError::ProtocolViolation(_))
+                        )
+                    {
+                        error!("Not reconnecting after protocol violation");
+                        return Err(e);
+                    }
+
+                    // Retrying with the same credentials cannot succeed
+                    if let Some(ProviderError::Unauthorized { .. }) = e.downcast_ref() {
+                        error!("Not reconnecting after the server rejected the credentials");
+                        return Err(e);
+                    }
 
                     // Check if we should retry
                     if self.config.max_reconnect_attempts > 0
@@ -58,12 +847,70 @@ impl WebSocketClient {
                     }
 
                     reconnect_attempts += 1;
+                    Span::current().record("attempt", reconnect_attempts);
+                    // Losing an established connection is not a failure of its URL
+                    if !established {
+                        url_failures += 1;
+                    }
+                    if !self.config.websocket_urls.is_empty()
+                        && url_failures >= self.config.attempts_per_url()
+                    {
+                        url_failures = 0;
+                        let url = self.next_url();
+                        warn!("Failing over to {}", url);
+                        Span::current().record("url", url.as_str());
+                    }
+                    self.lock_stats().record_reconnect();
+                    #[cfg(feature = "prometheus")]
+                    crate::prometheus::record_reconnect();
+
+                    // Persistent failures open the breaker, replacing the backoff
+                    if let Some(suspend) = breaker.record_failure() {
+                        self.set_status(ConnectionStatus::Reconnecting {
+                            attempt: reconnect_attempts,
+                        });
+                        self.record(TimelineEvent::Reconnecting {
+                            attempt: reconnect_attempts,
+                            delay_ms: suspend.as_millis() as u64,
+                        });
+                        self.emit_status(
+                            &mut message_handler,
+                            "reconnecting",
+                            Some(reconnect_attempts),
+                        );
+                        if self
+                            .backoff(suspend, &mut heartbeat, &mut message_handler)
+                            .await
+                        {
+                            breaker.half_open();
+                        }
+                        continue;
+                    }
+
+                    let delay = reconnect_delay(
+                        current_delay,
+                        self.config.reconnect_jitter_pct,
+                        self.config.reconnect_spread(),
+                    );
                     warn!(
                         "Attempting reconnection #{} after {:?}",
-                        reconnect_attempts, current_delay
+                        reconnect_attempts, delay
                     );
 
-                    sleep(current_delay).await;
+                    self.set_status(ConnectionStatus::Reconnecting {
+                        attempt: reconnect_attempts,
+                    });
+                    self.record(TimelineEvent::Reconnecting {
+                        attempt: reconnect_attempts,
+                        delay_ms: delay.as_millis() as u64,
+                    });
+                    self.emit_status(
+                        &mut message_handler,
+                        "reconnecting",
+                        Some(reconnect_attempts),
+                    );
+                    self.backoff(delay, &mut heartbeat, &mut message_handler)
+                        .await;
 
                     // Exponential backoff with max limit
                     current_delay =
@@ -74,85 +921,2059 @@ impl WebSocketClient {
     }
 
     /// Connect to WebSocket server and receive messages
-    async fn connect_and_receive<F>(&self, message_handler: &mut F) -> anyhow::Result<()>
+    async fn connect_and_receive<F>(
+        &self,
+        message_handler: &mut F,
+        outage_started: &mut Option<Instant>,
+        heartbeat: &mut Option<Interval>,
+        hooks: &Hooks<'_>,
+    ) -> anyhow::Result<()>
     where
-        F: FnMut(Vec<u8>) -> anyhow::Result<()>,
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
     {
-        info!(
-            "Connecting to WebSocket server: {}",
-            self.config.websocket_url
-        );
+        self.set_status(ConnectionStatus::Connecting);
+        self.record(TimelineEvent::Connecting);
+        let ws_stream = self.connect().await?;
+        self.set_status(ConnectionStatus::Connected);
+        self.record(TimelineEvent::Connected);
+        self.emit_status(message_handler, "connected", None);
+        (hooks.on_connect)();
+
+        if let Some(started) = outage_started.take() {
+            let outage = started.elapsed();
+            debug!("Forwarding resume marker after {:?} outage", outage);
+            message_handler(WebSocketMessage::text(resume_message(outage)))?;
+        }
+
+        let result = self.receive(ws_stream, message_handler, heartbeat).await;
+        // Nothing batched may be lost or overtaken by the markers below
+        if let Err(e) = self.flush_batch(message_handler) {
+            warn!("Failed to forward batch on disconnect: {}", e);
+        }
+        self.record(TimelineEvent::Disconnected);
+        self.emit_status(message_handler, "disconnected", None);
+
+        // An error means the client will try to reconnect, so open a gap
+        if let Err(e) = &result {
+            (hooks.on_disconnect)(e);
+            if self.config.emit_gap_markers {
+                debug!("Forwarding gap marker: {}", e);
+                message_handler(WebSocketMessage::text(gap_message(&e.to_string())))?;
+                *outage_started = Some(Instant::now());
+            }
+        }
+
+        result
+    }
+
+    /// Forward a final marker inline with the data so consumers can finalize
+    ///
+    /// Called once the client has stopped for good: after a drain, a normal
+    /// close or when it gives up reconnecting. Disconnects the client
+    /// recovers from get a gap marker instead.
+    fn emit_end_of_stream<F>(&self, message_handler: &mut F, result: &anyhow::Result<()>)
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        if !self.config.emit_eos_message {
+            return;
+        }
+        let reason = match result {
+            Ok(_) if self.drain.is_cancelled() => "drained".to_string(),
+            Ok(_) => "stream ended".to_string(),
+            Err(e) => e.to_string(),
+        };
+        debug!("Forwarding end-of-stream message: {}", reason);
+        if let Err(e) = message_handler(WebSocketMessage::text(end_of_stream_message(&reason))) {
+            warn!("Failed to forward end-of-stream message: {}", e);
+        }
+    }
+
+    /// Establish the WebSocket connection
+    ///
+    /// Tries each of [`connection_urls`] in turn, so with `auto_upgrade_tls`
+    /// a plaintext fallback is only attempted once TLS has failed.
+    async fn connect(&self) -> anyhow::Result<WsStream> {
+        let url = Url::parse(&self.current_url())?;
+
+        let mut candidates = connection_urls(&self.config, &url)?.into_iter().peekable();
+        while let Some(candidate) = candidates.next() {
+            match self.connect_to(&candidate).await {
+                Ok(ws_stream) => return Ok(ws_stream),
+                Err(e) => match candidates.peek() {
+                    Some(next) => warn!(
+                        "Connecting to {} failed, falling back to {}: {}",
+                        candidate, next, e
+                    ),
+                    None => return Err(e),
+                },
+            }
+        }
+
+        Err(require_tls_error(&url))
+    }
+
+    /// Connect to a single URL
+    async fn connect_to(&self, url: &Url) -> anyhow::Result<WsStream> {
+        info!("Connecting to WebSocket server: {}", url);
 
         // Use TLS connector for wss:// URLs, plain for ws://
-        let connector = if self.config.websocket_url.starts_with("wss://") {
+        let connector = if url.scheme() == "wss" {
             info!("Using TLS (rustls) for wss:// connection");
-            Some(build_tls_connector())
+            Some(build_tls_connector(&self.config)?)
         } else {
             None
         };
 
-        let (ws_stream, response) = connect_async_tls_with_config(
-            &self.config.websocket_url,
-            None,
-            false,
-            connector,
+        // Connect TCP first so the upgrade can be timed out on its own
+        let tcp_stream = connect_tcp(&self.config, url).await?;
+        // Behind a proxy the peer is the proxy, not the server
+        let server_ip = self
+            .config
+            .proxy(url)
+            .is_none()
+            .then(|| tcp_stream.peer_addr().ok())
+            .flatten()
+            .map(|addr| addr.ip().to_string());
+
+        let request = handshake_request(&self.config, url)?;
+        debug!("Request headers: {:?}", redacted_headers(request.headers()));
+
+        let handshake_timeout = self.config.handshake_timeout();
+        let (ws_stream, response) = timeout(
+            handshake_timeout,
+            client_async_tls_with_config(request, tcp_stream, None, connector),
         )
-        .await?;
+        .await
+        .map_err(|_| handshake_timeout_error(handshake_timeout))?
+        .map_err(|e| match e {
+            tungstenite::Error::Protocol(ProtocolError::SecWebSocketSubProtocolError(reason)) => {
+                ProviderError::SubprotocolMismatch {
+                    requested: self.config.subprotocols.clone(),
+                    reason: reason.to_string(),
+                }
+                .into()
+            }
+            tungstenite::Error::Http(response)
+                if matches!(response.status().as_u16(), 401 | 403) =>
+            {
+                ProviderError::Unauthorized {
+                    status: response.status().as_u16(),
+                }
+                .into()
+            }
+            e => anyhow::Error::from(e),
+        })?;
 
         info!("WebSocket connection established: {:?}", response.status());
-        debug!("Response headers: {:?}", response.headers());
+        debug!(
+            "Response headers: {:?}",
+            redacted_headers(response.headers())
+        );
+
+        let subprotocol = response
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        if let Some(protocol) = &subprotocol {
+            info!("Server selected subprotocol: {}", protocol);
+        }
+        let mut session = self.lock_session();
+        *session = Session {
+            subprotocol,
+            server_ip,
+            epoch: session.epoch + 1,
+            connected_at: Some(Instant::now()),
+            sequence: 0,
+        };
+
+        Ok(ws_stream)
+    }
+
+    /// Receive messages until the connection closes
+    async fn receive<F>(
+        &self,
+        ws_stream: WsStream,
+        message_handler: &mut F,
+        heartbeat: &mut Option<Interval>,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        let (mut write, mut read) = ws_stream.split();
+
+        self.send_subscription(&mut write).await?;
+
+        // Periodically resend the subscription while the connection stays up
+        let mut resubscribe = self
+            .config
+            .resubscribe_interval()
+            .map(|period| interval_at(Instant::now() + period, period));
+
+        // Our own Pings; a missing Pong means the connection is dead
+        let mut ping = self
+            .config
+            .ping()
+            .map(|(period, _)| interval_at(Instant::now() + period, period));
+        let mut pong_deadline = None;
+
+        // Survives only this connection; a reconnect starts with a full bucket
+        let mut rate_limit = (self.config.max_messages_per_sec > 0)
+            .then(|| TokenBucket::new(self.config.max_messages_per_sec));
 
-        let (_, mut read) = ws_stream.split();
+        // A frame held back by the `delay` policy; nothing is read until it is forwarded
+        let mut delayed: Option<(Message, Instant)> = None;
+
+        let idle_timeout = self.config.idle_timeout();
+        let mut last_activity = Instant::now();
+        let mut outbound = self.outbound_rx.lock().await;
 
         // Receive messages
-        while let Some(message_result) = read.next().await {
-            match message_result {
-                Ok(message) => match message {
-                    Message::Text(text) => {
-                        debug!("Received text message: {} bytes", text.len());
-                        if text.len() > self.config.max_message_size {
-                            warn!(
-                                "Message size {} exceeds limit {}, skipping",
-                                text.len(),
-                                self.config.max_message_size
-                            );
-                            continue;
+        loop {
+            let batch_deadline = self.lock_batch().deadline;
+            tokio::select! {
+                message_result = read.next(), if delayed.is_none() => {
+                    let Some(message_result) = message_result else {
+                        break;
+                    };
+                    match message_result {
+                        // Frames racing a drain request are dropped
+                        Ok(_) if self.drain.is_cancelled() => {}
+                        Ok(message) => {
+                            if self.is_activity(&message) {
+                                last_activity = Instant::now();
+                            }
+                            if let Message::Pong(_) = message {
+                                pong_deadline = None;
+                            }
+                            if let (Some(bucket), true) =
+                                (&mut rate_limit, message.is_text() || message.is_binary())
+                            {
+                                match self.throttle(bucket, &message) {
+                                    Throttle::Forward => {}
+                                    Throttle::Drop => continue,
+                                    Throttle::Delay(until) => {
+                                        delayed = Some((message, until));
+                                        continue;
+                                    }
+                                }
+                            }
+                            self.handle_message(message, message_handler)?;
                         }
-                        message_handler(text.into_bytes())?;
-                    }
-                    Message::Binary(data) => {
-                        debug!("Received binary message: {} bytes", data.len());
-                        if data.len() > self.config.max_message_size {
-                            warn!(
-                                "Message size {} exceeds limit {}, skipping",
-                                data.len(),
-                                self.config.max_message_size
-                            );
-                            continue;
+                        Err(e @ (tungstenite::Error::Protocol(_) | tungstenite::Error::Utf8)) => {
+                            error!("Server violated the WebSocket protocol: {}", e);
+                            self.lock_stats().record_protocol_violation();
+                            return Err(ProviderError::ProtocolViolation(e.to_string()).into());
+                        }
+                        Err(e) => {
+                            error!("Error receiving message: {}", e);
+                            return Err(e.into());
                         }
-                        message_handler(data)?;
                     }
-                    Message::Ping(_) => {
-                        debug!("Received ping");
+                }
+                _ = sleep_until_deadline(delayed.as_ref().map(|(_, until)| *until)) => {
+                    let Some((message, _)) = delayed.take() else {
+                        continue;
+                    };
+                    match rate_limit.as_mut().map(TokenBucket::try_acquire) {
+                        Some(Err(wait)) => delayed = Some((message, Instant::now() + wait)),
+                        _ => self.handle_message(message, message_handler)?,
                     }
-                    Message::Pong(_) => {
-                        debug!("Received pong");
+                }
+                _ = sleep_until_deadline(idle_timeout.map(|timeout| last_activity + timeout)) => {
+                    warn!(
+                        "No activity for {:?}, treating connection as dead",
+                        idle_timeout.unwrap_or_default()
+                    );
+                    anyhow::bail!("Connection idle timeout");
+                }
+                _ = tick(&mut ping) => {
+                    debug!("Sending ping");
+                    write.send(Message::Ping(Vec::new())).await?;
+                    if let (None, Some((_, pong_timeout))) = (pong_deadline, self.config.ping()) {
+                        pong_deadline = Some(Instant::now() + pong_timeout);
                     }
-                    Message::Close(frame) => {
-                        info!("Received close frame: {:?}", frame);
-                        return Err(anyhow::anyhow!("Connection closed"));
+                }
+                _ = sleep_until_deadline(batch_deadline) => {
+                    self.flush_batch(message_handler)?;
+                }
+                _ = sleep_until_deadline(pong_deadline) => {
+                    warn!("No pong received in time, treating connection as dead");
+                    anyhow::bail!("Pong timeout");
+                }
+                _ = tick(heartbeat) => {
+                    if let Err(e) = self.emit_heartbeat(message_handler, true) {
+                        warn!("Failed to forward heartbeat: {}", e);
                     }
-                    Message::Frame(_) => {
-                        debug!("Received raw frame");
+                }
+                Some(frame) = outbound.recv() => {
+                    debug!("Sending outbound frame: {} bytes", frame.len());
+                    write.send(frame).await?;
+                }
+                _ = tick(&mut resubscribe) => {
+                    debug!("Resubscribe interval elapsed");
+                    self.send_subscription(&mut write).await?;
+                }
+                _ = self.drain.cancelled() => {
+                    info!("Draining: no longer forwarding frames, closing connection");
+                    if let Err(e) = write.close().await {
+                        debug!("Failed to close WebSocket cleanly: {}", e);
                     }
-                },
-                Err(e) => {
-                    error!("Error receiving message: {}", e);
-                    return Err(e.into());
+                    break;
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Send the configured subscription payload, if any
+    async fn send_subscription(&self, write: &mut WsSink) -> anyhow::Result<()> {
+        if let Some(payload) = &self.config.on_connect_send {
+            debug!("Sending subscription payload: {} bytes", payload.len());
+            write.send(Message::Text(payload.clone())).await?;
+        }
+        Ok(())
+    }
+
+    /// Apply `max_messages_per_sec` to a data frame
+    ///
+    /// Under the `delay` policy the caller holds the frame until the returned
+    /// deadline and stops reading meanwhile, while still serving its timers
+    /// and outbound frames.
+    fn throttle(&self, bucket: &mut TokenBucket, message: &Message) -> Throttle {
+        let Err(wait) = bucket.try_acquire() else {
+            return Throttle::Forward;
+        };
+        match self.config.rate_limit_policy {
+            RateLimitPolicy::Drop => {
+                let message_type = match message {
+                    Message::Binary(_) => MessageType::Binary,
+                    _ => MessageType::Text,
+                };
+                debug!("Rate limit exceeded, dropping message");
+                self.record_received(message_type, message.len());
+                self.lock_stats().record_throttled(false);
+                Throttle::Drop
+            }
+            RateLimitPolicy::Delay => {
+                self.lock_stats().record_throttled(true);
+                Throttle::Delay(Instant::now() + wait)
+            }
+        }
+    }
+
+    /// Wait out a reconnect delay, heartbeating as disconnected meanwhile
+    ///
+    /// Returns false when a drain request cut the wait short.
+    async fn backoff<F>(
+        &self,
+        delay: Duration,
+        heartbeat: &mut Option<Interval>,
+        message_handler: &mut F,
+    ) -> bool
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        let deadline = Instant::now() + delay;
+        loop {
+            tokio::select! {
+                _ = sleep_until(deadline) => return true,
+                _ = self.drain.cancelled() => return false,
+                _ = tick(heartbeat) => {
+                    if let Err(e) = self.emit_heartbeat(message_handler, false) {
+                        warn!("Failed to forward heartbeat: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Forward a heartbeat to `heartbeat_subject`, if configured
+    fn emit_heartbeat<F>(&self, message_handler: &mut F, connected: bool) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        let Some((subject, _)) = self.config.heartbeat() else {
+            return Ok(());
+        };
+        debug!("Publishing heartbeat to {}", subject);
+        let json = heartbeat_message(
+            self.source_id.as_deref(),
+            &self.current_url(),
+            connected,
+            self.lock_stats().messages_received(),
+        );
+        message_handler(WebSocketMessage::control(subject, json))
+    }
+
+    /// Forward a lifecycle event to `status_subject`, if configured
+    ///
+    /// Failures are logged and ignored, so status reporting never stops the data path.
+    fn emit_status<F>(&self, message_handler: &mut F, event: &str, attempt: Option<u32>)
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        let Some(subject) = &self.config.status_subject else {
+            return;
+        };
+        let json = status_message(event, &self.current_url(), attempt);
+        if let Err(e) = message_handler(WebSocketMessage::control(subject, json)) {
+            warn!("Failed to forward {} status event: {}", event, e);
+        }
+    }
+
+    /// Run a data message through the configured pipeline and forward the results
+    fn forward<F>(&self, message: WebSocketMessage, message_handler: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        // A pending request takes the next message as its reply
+        let waiter = self.lock_reply_waiter().take();
+        let message = match waiter {
+            Some(waiter) => match waiter.send(message) {
+                Ok(()) => return Ok(()),
+                // The requester gave up, so forward the message as usual
+                Err(message) => message,
+            },
+            None => message,
+        };
+
+        if let Some(filter) = &self.config.message_filter {
+            let keep = filter
+                .matches(&message)
+                .unwrap_or(!self.config.message_filter_drop_non_json);
+            if !keep {
+                debug!("Message did not match message_filter, not forwarding");
+                self.lock_stats().record_filtered();
+                return Ok(());
+            }
+        }
+
+        let messages = if self.config.pipeline.is_empty() {
+            vec![message]
+        } else {
+            match pipeline::run(&self.config.pipeline, message) {
+                Ok(messages) => messages,
+                Err(e) => {
+                    warn!("Pipeline failed, dropping message: {}", e);
+                    self.lock_stats().record_dropped();
+                    return Ok(());
+                }
+            }
+        };
+
+        for mut message in messages {
+            if self.config.enrich_metadata {
+                message.connection = Some(self.connection_metadata());
+            }
+            self.deliver(message, message_handler)?;
+        }
+        Ok(())
+    }
+
+    /// Hand a data message to the handler, or add it to the batch when batching
+    fn deliver<F>(&self, message: WebSocketMessage, message_handler: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        if self.config.batch_size == 0 {
+            return message_handler(message);
+        }
+        let full = {
+            let mut batch = self.lock_batch();
+            if batch.messages.is_empty() {
+                batch.deadline = Some(Instant::now() + self.config.batch_timeout());
+            }
+            batch.messages.push(message);
+            batch.messages.len() >= self.config.batch_size
+        };
+        if full {
+            self.flush_batch(message_handler)?;
+        }
+        Ok(())
+    }
+
+    /// Forward the pending batch as one JSON array message, if any
+    fn flush_batch<F>(&self, message_handler: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        let messages = {
+            let mut batch = self.lock_batch();
+            batch.deadline = None;
+            std::mem::take(&mut batch.messages)
+        };
+        if messages.is_empty() {
+            return Ok(());
+        }
+        debug!("Forwarding batch of {} messages", messages.len());
+        let entries = messages
+            .iter()
+            .map(WebSocketMessage::to_batch_entry)
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        message_handler(WebSocketMessage::text(serde_json::to_string(&entries)?))
+    }
+
+    fn lock_batch(&self) -> std::sync::MutexGuard<'_, Batch> {
+        self.batch
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Split a text frame on the delimiter and forward each non-empty segment
+    ///
+    /// Frames with more than `max_segments_per_frame` segments are dropped
+    /// whole, so a single oversized frame cannot cause a forwarding storm.
+    fn forward_segments<F>(
+        &self,
+        text: &str,
+        delimiter: &str,
+        message_handler: &mut F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        let segments: Vec<&str> = text
+            .split(delimiter)
+            .filter(|segment| !segment.trim().is_empty())
+            .collect();
+
+        if self.config.max_segments_per_frame > 0
+            && segments.len() > self.config.max_segments_per_frame
+        {
+            warn!(
+                "Frame has {} segments, exceeding limit {}, dropping frame",
+                segments.len(),
+                self.config.max_segments_per_frame
+            );
+            return Ok(());
+        }
+
+        for segment in segments {
+            self.forward(WebSocketMessage::text(segment), message_handler)?;
+        }
+        Ok(())
+    }
+
+    /// Whether a message counts as activity for the idle timer
+    ///
+    /// Data frames always count. Ping/Pong frames only count when
+    /// `control_frames_as_activity` is set, for servers that use unsolicited
+    /// Pongs as heartbeats.
+    fn is_activity(&self, message: &Message) -> bool {
+        match message {
+            Message::Text(_) | Message::Binary(_) => true,
+            Message::Ping(_) | Message::Pong(_) => self.config.control_frames_as_activity,
+            Message::Close(_) | Message::Frame(_) => false,
+        }
+    }
+
+    /// Check a received payload against `max_message_size` (0 for unlimited)
+    fn check_size(&self, size: usize) -> ProviderResult<()> {
+        let limit = self.config.max_message_size;
+        if limit != 0 && size > limit {
+            return Err(ProviderError::MessageTooLarge { size, limit });
+        }
+        Ok(())
+    }
+
+    /// Handle a single message received from the server
+    fn handle_message<F>(&self, message: Message, message_handler: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(WebSocketMessage) -> anyhow::Result<()>,
+    {
+        match message {
+            Message::Text(text) => {
+                debug!("Received text message: {} bytes", text.len());
+                self.record_received(MessageType::Text, text.len());
+                if let Err(e) = self.check_size(text.len()) {
+                    warn!("Dropping message: {}", e);
+                    self.lock_stats().record_oversized_message();
+                    return Ok(());
+                }
+                match &self.config.split_delimiter {
+                    Some(delimiter) => self.forward_segments(&text, delimiter, message_handler)?,
+                    None => self.forward(WebSocketMessage::text(text), message_handler)?,
+                }
+            }
+            Message::Binary(data) => {
+                debug!("Received binary message: {} bytes", data.len());
+                self.record_received(MessageType::Binary, data.len());
+                if let Err(e) = self.check_size(data.len()) {
+                    warn!("Dropping message: {}", e);
+                    self.lock_stats().record_oversized_message();
+                    return Ok(());
+                }
+                self.forward(WebSocketMessage::binary(data), message_handler)?;
+            }
+            Message::Ping(_) => {
+                debug!("Received ping");
+            }
+            Message::Pong(_) => {
+                debug!("Received pong");
+            }
+            Message::Close(frame) => {
+                info!("Received close frame: {:?}", frame);
+                self.record(TimelineEvent::Closed {
+                    reason: frame
+                        .as_ref()
+                        .map(|frame| frame.reason.to_string())
+                        .unwrap_or_default(),
+                });
+                return match frame {
+                    Some(frame) => Err(anyhow::anyhow!(
+                        "Connection closed ({}): {}",
+                        frame.code,
+                        frame.reason
+                    )),
+                    None => Err(anyhow::anyhow!("Connection closed")),
+                };
+            }
+            Message::Frame(_) => {
+                debug!("Received raw frame");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::net::TcpListener;
+    use tokio::time::sleep;
+
+    use super::*;
+
+    /// Limit on how long a test waits for the client
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A listener on a free local port and its ws:// URL
+    async fn listen() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        (listener, url)
+    }
+
+    /// Accept one connection and complete the WebSocket handshake
+    async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio_tungstenite::accept_async(stream).await.unwrap()
+    }
+
+    fn config(url: &str, pairs: &[(&str, &str)]) -> LinkConfig {
+        let mut values: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        values.insert("websocket_url".to_string(), url.to_string());
+        LinkConfig::from_values(&values).unwrap()
+    }
+
+    fn text(message: &WebSocketMessage) -> &str {
+        std::str::from_utf8(&message.payload).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lost_fallback_connection_returns_to_the_primary() {
+        let (primary, primary_url) = listen().await;
+        let (fallback, fallback_url) = listen().await;
+        tokio::spawn(async move {
+            // Fail the first attempt before the handshake
+            drop(primary.accept().await.unwrap());
+            let mut ws = accept(&primary).await;
+            ws.send(Message::Text("primary".into())).await.unwrap();
+            let _ = ws.next().await;
+        });
+        tokio::spawn(async move {
+            // Keeps accepting, so a reconnect that wrongly lands here is seen
+            loop {
+                let mut ws = accept(&fallback).await;
+                ws.send(Message::Text("fallback".into())).await.unwrap();
+                ws.close(None).await.unwrap();
+            }
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &primary_url,
+            &[
+                ("websocket_urls", &fallback_url),
+                ("initial_reconnect_delay_ms", "10"),
+                ("max_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+
+        for expected in ["fallback", "primary"] {
+            let message = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+            assert_eq!(text(&message), expected);
+        }
+        assert_eq!(client.current_url(), primary_url);
+
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_down_primary_fails_over_to_the_secondary() {
+        let (primary, primary_url) = listen().await;
+        drop(primary);
+        let (secondary, secondary_url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&secondary).await;
+            ws.send(Message::Text("secondary".into())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &primary_url,
+            &[
+                ("websocket_urls", &secondary_url),
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+
+        let message = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(text(&message), "secondary");
+        assert_eq!(client.current_url(), secondary_url);
+
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn require_tls_never_connects_in_plaintext() {
+        let (listener, url) = listen().await;
+        // Bypasses from_values, which already rejects this combination
+        let mut config = config(&url, &[]);
+        config.require_tls = true;
+
+        let err = WebSocketClient::new(config).connect().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProviderError>(),
+            Some(ProviderError::ConfigError(_))
+        ));
+        assert!(timeout(Duration::from_millis(50), listener.accept())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn rejected_credentials_fail_without_retrying() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+        // The callback signature is tungstenite's, large error and all
+        #[allow(clippy::result_large_err)]
+        fn require_authorization(
+            request: &Request,
+            response: Response,
+        ) -> Result<Response, ErrorResponse> {
+            if request.headers().contains_key(AUTHORIZATION) {
+                return Ok(response);
+            }
+            let mut refusal = ErrorResponse::new(None);
+            *refusal.status_mut() = tungstenite::http::StatusCode::UNAUTHORIZED;
+            Err(refusal)
+        }
+
+        let (listener, url) = listen().await;
+        let attempts = Arc::new(AtomicUsize::new(0));
+        tokio::spawn({
+            let attempts = attempts.clone();
+            async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    let _ =
+                        tokio_tungstenite::accept_hdr_async(stream, require_authorization).await;
+                }
+            }
+        });
+
+        let client = WebSocketClient::new(config(
+            &url,
+            &[
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        ));
+        let err = timeout(TEST_TIMEOUT, client.run(|_| Ok(())))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProviderError>(),
+            Some(ProviderError::Unauthorized { status: 401 })
+        ));
+        assert_eq!(client.status(), ConnectionStatus::Failed);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // The same server accepts the bearer token
+        let client = WebSocketClient::new(config(&url, &[("auth_token", "secret")]));
+        timeout(TEST_TIMEOUT, client.connect())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn reconnect_spread_stays_within_its_window() {
+        let base = Duration::from_millis(1000);
+        let spread = Duration::from_millis(500);
+        assert_eq!(reconnect_delay(base, 0, Duration::ZERO), base);
+
+        let delays: Vec<_> = (0..1000)
+            .map(|_| reconnect_delay(base, 0, spread))
+            .collect();
+        assert!(delays
+            .iter()
+            .all(|delay| *delay >= base && *delay <= base + spread));
+        // The spread is applied, not just allowed
+        assert!(delays.iter().any(|delay| *delay > base + spread / 2));
+    }
+
+    #[tokio::test]
+    async fn reconnect_backoff_is_jittered_around_each_doubling() {
+        let (listener, url) = listen().await;
+        drop(listener);
+
+        let client = WebSocketClient::new(config(
+            &url,
+            &[
+                ("max_reconnect_attempts", "4"),
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "50"),
+            ],
+        ));
+        assert!(timeout(TEST_TIMEOUT, client.run(|_| Ok(())))
+            .await
+            .unwrap()
+            .is_err());
+
+        let delays: Vec<_> = client
+            .timeline()
+            .into_iter()
+            .filter_map(|(_, event)| match event {
+                TimelineEvent::Reconnecting { delay_ms, .. } => Some(delay_ms),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(delays.len(), 4);
+        for (delay, base) in delays.into_iter().zip([10, 20, 40, 80]) {
+            assert!(
+                (base / 2..=base * 3 / 2).contains(&delay),
+                "{delay}ms is outside the jitter around {base}ms"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn a_silent_server_fails_the_handshake_timeout() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            // Accept the TCP connection but never answer the upgrade
+            let (_stream, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let client = WebSocketClient::new(config(&url, &[("handshake_timeout_ms", "50")]));
+        let err = timeout(TEST_TIMEOUT, client.connect())
+            .await
+            .unwrap()
+            .unwrap_err();
+        match err.downcast_ref::<ProviderError>() {
+            Some(ProviderError::Timeout { operation, timeout }) => {
+                assert_eq!(operation, "websocket_handshake");
+                assert_eq!(*timeout, Duration::from_millis(50));
+            }
+            other => panic!("expected a handshake timeout, got {:?}", other),
+        }
+    }
+
+    /// A wss://localhost server speaking only `version`, and a CA file trusting it
+    async fn tls_server(
+        version: &'static rustls::SupportedProtocolVersion,
+    ) -> (String, std::path::PathBuf) {
+        let certified = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+        let server_config = rustls::ServerConfig::builder_with_provider(
+            rustls::crypto::ring::default_provider().into(),
+        )
+        .with_protocol_versions(&[version])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![certified.cert.der().clone()],
+            PrivateKeyDer::Pkcs8(certified.key_pair.serialize_der().into()),
+        )
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+        let (listener, url) = listen().await;
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(tls) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    if let Ok(mut ws) = tokio_tungstenite::accept_async(tls).await {
+                        while ws.next().await.is_some() {}
+                    }
+                });
+            }
+        });
+
+        let ca_path = std::env::temp_dir().join(format!(
+            "websocket-test-ca-{}-{}.pem",
+            std::process::id(),
+            port
+        ));
+        std::fs::write(&ca_path, certified.cert.pem()).unwrap();
+        let url = url.replace("ws://127.0.0.1", "wss://localhost");
+        (url, ca_path)
+    }
+
+    #[tokio::test]
+    async fn tls_min_version_refuses_an_older_server() {
+        let (url, ca_path) = tls_server(&rustls::version::TLS12).await;
+        let ca = ca_path.to_str().unwrap();
+
+        let client = WebSocketClient::new(config(&url, &[("ca_cert_path", ca)]));
+        let connected = timeout(TEST_TIMEOUT, client.connect()).await.unwrap();
+        assert!(connected.is_ok(), "{:?}", connected.err());
+
+        let client = WebSocketClient::new(config(
+            &url,
+            &[("ca_cert_path", ca), ("tls_min_version", "1.3")],
+        ));
+        let refused = timeout(TEST_TIMEOUT, client.connect()).await.unwrap();
+        std::fs::remove_file(&ca_path).unwrap();
+        let err = refused.unwrap_err().to_string();
+        assert!(err.contains("ProtocolVersion"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn auto_upgrade_tls_prefers_wss_and_falls_back_to_plaintext() {
+        // A TLS server reached through a ws:// URL is upgraded
+        let (url, ca_path) = tls_server(&rustls::version::TLS13).await;
+        let url = url.replace("wss://", "ws://");
+        let client = WebSocketClient::new(config(
+            &url,
+            &[
+                ("auto_upgrade_tls", "true"),
+                ("ca_cert_path", ca_path.to_str().unwrap()),
+            ],
+        ));
+        let connected = timeout(TEST_TIMEOUT, client.connect()).await.unwrap();
+        std::fs::remove_file(&ca_path).unwrap();
+        assert!(matches!(
+            connected.unwrap().get_ref(),
+            MaybeTlsStream::Rustls(_)
+        ));
+
+        // A plaintext server is still reached once the TLS attempt fails
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            // The first connection is the failed TLS attempt
+            drop(listener.accept().await.unwrap());
+            let mut ws = accept(&listener).await;
+            while ws.next().await.is_some() {}
+        });
+        let client = WebSocketClient::new(config(&url, &[("auto_upgrade_tls", "true")]));
+        let connected = timeout(TEST_TIMEOUT, client.connect()).await.unwrap();
+        assert!(matches!(
+            connected.unwrap().get_ref(),
+            MaybeTlsStream::Plain(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn require_tls_refuses_the_plaintext_fallback() {
+        let (listener, url) = listen().await;
+        let accepted = tokio::spawn(async move {
+            let mut attempts = 0;
+            while let Ok(Ok((stream, _))) =
+                timeout(Duration::from_millis(200), listener.accept()).await
+            {
+                attempts += 1;
+                drop(stream);
+            }
+            attempts
+        });
+
+        let client = WebSocketClient::new(config(
+            &url,
+            &[("auto_upgrade_tls", "true"), ("require_tls", "true")],
+        ));
+        assert!(timeout(TEST_TIMEOUT, client.connect())
+            .await
+            .unwrap()
+            .is_err());
+        // Only the wss:// attempt reached the server
+        assert_eq!(accepted.await.unwrap(), 1);
+    }
+
+    #[test]
+    fn jittered_stays_within_the_spread() {
+        let delay = Duration::from_millis(1000);
+        assert_eq!(jittered(delay, 0), delay);
+        for _ in 0..1000 {
+            let jittered = jittered(delay, 20);
+            assert!(jittered >= Duration::from_millis(800));
+            assert!(jittered <= Duration::from_millis(1200));
+        }
+        // Spreads above 100% are capped, so the delay never goes negative
+        for _ in 0..1000 {
+            assert!(jittered(delay, 255) <= Duration::from_millis(2000));
+        }
+    }
+
+    /// Serve one connection that sends `text` and then closes with `reason`
+    async fn send_and_close(text: &'static str, reason: &'static str) -> String {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text(text.into())).await.unwrap();
+            ws.close(Some(tungstenite::protocol::CloseFrame {
+                code: tungstenite::protocol::frame::coding::CloseCode::Normal,
+                reason: reason.into(),
+            }))
+            .await
+            .unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn end_of_stream_message_follows_a_server_close() {
+        let url = send_and_close("last trade", "maintenance").await;
+        let client = WebSocketClient::new(config(&url, &[("emit_eos_message", "true")]));
+
+        let mut messages = Vec::new();
+        let result = client
+            .connect_once(|message| {
+                messages.push(message);
+                Ok(())
+            })
+            .await;
+
+        assert!(result.unwrap_err().to_string().contains("maintenance"));
+        assert_eq!(client.status(), ConnectionStatus::Failed);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(text(&messages[0]), "last trade");
+        let eos: serde_json::Value = serde_json::from_slice(&messages[1].payload).unwrap();
+        assert_eq!(eos["websocket_eos"], true);
+        assert!(eos["reason"].as_str().unwrap().contains("maintenance"));
+    }
+
+    #[tokio::test]
+    async fn end_of_stream_waits_for_the_client_to_stop() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("before".into())).await.unwrap();
+            ws.close(None).await.unwrap();
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("after".into())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("emit_eos_message", "true"),
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+
+        // The server close is followed by a reconnect, not an end-of-stream marker
+        for expected in ["before", "after"] {
+            let message = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+            assert_eq!(text(&message), expected);
+        }
+
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+        let eos = rx.recv().await.unwrap();
+        let eos: serde_json::Value = serde_json::from_slice(&eos.payload).unwrap();
+        assert_eq!(eos["websocket_eos"], true);
+        assert_eq!(eos["reason"], "drained");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn server_close_opens_a_gap_until_reconnected() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            for text in ["before", "after"] {
+                let mut ws = accept(&listener).await;
+                ws.send(Message::Text(text.into())).await.unwrap();
+                ws.close(None).await.unwrap();
+            }
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("emit_gap_markers", "true"),
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            let message = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+            received.push(
+                serde_json::from_slice(&message.payload)
+                    .unwrap_or_else(|_| serde_json::Value::from(text(&message))),
+            );
+        }
+        assert_eq!(received[0], "before");
+        assert_eq!(received[1]["websocket_gap"], true);
+        assert_eq!(received[2]["websocket_resume"], true);
+        assert!(received[2]["outage_ms"].is_u64());
+        assert_eq!(received[3], "after");
+
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscription_is_replayed_after_a_reconnect() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            for connection in ["first", "second"] {
+                let mut ws = accept(&listener).await;
+                let Some(Ok(Message::Text(subscription))) = ws.next().await else {
+                    panic!("expected the subscription on the {} connection", connection);
+                };
+                ws.send(Message::Text(format!("{} {}", connection, subscription)))
+                    .await
+                    .unwrap();
+                // Drop the first connection so the client reconnects
+                if connection == "first" {
+                    ws.close(None).await.unwrap();
+                } else {
+                    while ws.next().await.is_some() {}
+                }
+            }
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("on_connect_send", "subscribe"),
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+
+        for expected in ["first subscribe", "second subscribe"] {
+            let message = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+            assert_eq!(text(&message), expected);
+        }
+
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+    }
+
+    /// Run one connection against a server that sends only Pongs for 20
+    /// seconds of paused time, then "done", with a 10 second idle timeout
+    async fn pong_only_traffic(control_frames_as_activity: &str) -> anyhow::Result<Vec<String>> {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            for _ in 0..5 {
+                sleep(Duration::from_secs(4)).await;
+                ws.send(Message::Pong(Vec::new())).await.unwrap();
+            }
+            ws.send(Message::Text("done".into())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("idle_timeout_secs", "10"),
+                ("control_frames_as_activity", control_frames_as_activity),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .connect_once(move |message| {
+                        tx.send(text(&message).to_string())?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        timeout(TEST_TIMEOUT, async {
+            while client.status() != ConnectionStatus::Connected {
+                sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Timers jump ahead from here; the idle deadline is already armed
+        tokio::time::pause();
+        let mut received = Vec::new();
+        if let Ok(Some(message)) = timeout(Duration::from_secs(60), rx.recv()).await {
+            received.push(message);
+            client.drain();
+        }
+        run.await.unwrap().map(|_| received)
+    }
+
+    #[tokio::test]
+    async fn pongs_keep_the_connection_alive_as_control_activity() {
+        let received = pong_only_traffic("true").await.unwrap();
+        assert_eq!(received, ["done"]);
+    }
+
+    #[tokio::test]
+    async fn pongs_alone_do_not_reset_the_idle_timer() {
+        let err = pong_only_traffic("false").await.unwrap_err();
+        assert!(err.to_string().contains("idle timeout"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn heartbeats_report_an_established_connection() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("tick".into())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("heartbeat_subject", "feed.heartbeat"),
+                ("heartbeat_interval_secs", "1"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        let data = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(text(&data), "tick");
+
+        tokio::time::pause();
+        let heartbeat = timeout(Duration::from_secs(60), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(heartbeat.subject.as_deref(), Some("feed.heartbeat"));
+        let json: serde_json::Value = serde_json::from_slice(&heartbeat.payload).unwrap();
+        assert_eq!(json["status"], "connected");
+        assert_eq!(json["connected"], true);
+        assert_eq!(json["messages_received"], 1);
+
+        client.drain();
+        run.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heartbeats_continue_while_reconnecting() {
+        let (listener, url) = listen().await;
+        drop(listener);
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("heartbeat_subject", "feed.heartbeat"),
+                ("heartbeat_interval_secs", "1"),
+                ("initial_reconnect_delay_ms", "60000"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+
+        // Several ticks land inside the first backoff
+        for _ in 0..3 {
+            let heartbeat = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+            assert_eq!(heartbeat.subject.as_deref(), Some("feed.heartbeat"));
+            let json: serde_json::Value = serde_json::from_slice(&heartbeat.payload).unwrap();
+            assert_eq!(json["status"], "disconnected");
+            assert_eq!(json["connected"], false);
+        }
+        assert!(matches!(
+            client.status(),
+            ConnectionStatus::Reconnecting { attempt: 1 }
+        ));
+
+        client.drain();
+        run.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_missing_pong_reconnects_after_the_pong_timeout() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            // Never read from the first connection, so its Pings go unanswered
+            let _silent = accept(&listener).await;
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("reconnected".into())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("ping_interval_secs", "5"),
+                ("pong_timeout_secs", "2"),
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let disconnects = Arc::new(Mutex::new(Vec::new()));
+        let run = tokio::spawn({
+            let client = client.clone();
+            let disconnects = disconnects.clone();
+            async move {
+                client
+                    .run_with_hooks(
+                        move |message| {
+                            tx.send(message)?;
+                            Ok(())
+                        },
+                        || {},
+                        move |e| disconnects.lock().unwrap().push(e.to_string()),
+                    )
+                    .await
+            }
+        });
+        timeout(TEST_TIMEOUT, async {
+            while client.status() != ConnectionStatus::Connected {
+                sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        tokio::time::pause();
+        let started = Instant::now();
+        let message = timeout(Duration::from_secs(60), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(text(&message), "reconnected");
+        // One ping interval, then the pong timeout
+        assert!(started.elapsed() >= Duration::from_secs(6));
+        assert_eq!(*disconnects.lock().unwrap(), ["Pong timeout"]);
+
+        client.drain();
+        run.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_established_connection_resets_the_backoff() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            // Two failed attempts grow the delay
+            for _ in 0..2 {
+                drop(listener.accept().await.unwrap());
+            }
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("up".into())).await.unwrap();
+            ws.close(None).await.unwrap();
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("again".into())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        for expected in ["up", "again"] {
+            let message = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+            assert_eq!(text(&message), expected);
+        }
+
+        // The lost connection starts over at attempt 1 and the initial delay
+        let backoffs: Vec<_> = client
+            .timeline()
+            .into_iter()
+            .filter_map(|(_, event)| match event {
+                TimelineEvent::Reconnecting { attempt, delay_ms } => Some((attempt, delay_ms)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(backoffs, [(1, 10), (2, 20), (1, 10)]);
+
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+    }
+
+    /// Lifecycle events with the client status seen when each was forwarded
+    type StatusLog = Arc<Mutex<Vec<(String, ConnectionStatus)>>>;
+
+    /// Run `client`, recording its status at every lifecycle event on `status`
+    fn run_recording_status(
+        client: &Arc<WebSocketClient>,
+    ) -> (tokio::task::JoinHandle<anyhow::Result<()>>, StatusLog) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let run = tokio::spawn({
+            let client = client.clone();
+            let seen = seen.clone();
+            async move {
+                let observed = client.clone();
+                client
+                    .run(move |message| {
+                        if message.subject.as_deref() == Some("status") {
+                            let json: serde_json::Value = serde_json::from_slice(&message.payload)?;
+                            let event = json["event"].as_str().unwrap_or_default().to_string();
+                            seen.lock().unwrap().push((event, observed.status()));
+                        }
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        (run, seen)
+    }
+
+    #[tokio::test]
+    async fn status_follows_the_connection_lifecycle_until_closed() {
+        let (listener, url) = listen().await;
+        let (close_tx, close_rx) = oneshot::channel::<()>();
+        let (handshake_tx, handshake_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            close_rx.await.unwrap();
+            ws.close(None).await.unwrap();
+            // Hold the reconnect in the handshake until the test has looked
+            let (stream, _) = listener.accept().await.unwrap();
+            handshake_rx.await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("status_subject", "status"),
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let (run, seen) = run_recording_status(&client);
+
+        let wait_for = |status: ConnectionStatus| {
+            let client = client.clone();
+            async move {
+                timeout(TEST_TIMEOUT, async {
+                    while client.status() != status {
+                        sleep(Duration::from_millis(1)).await;
+                    }
+                })
+                .await
+                .unwrap()
+            }
+        };
+        wait_for(ConnectionStatus::Connected).await;
+        close_tx.send(()).unwrap();
+        // Reconnecting comes and goes quickly, so it is checked in the events below
+        wait_for(ConnectionStatus::Connecting).await;
+        handshake_tx.send(()).unwrap();
+        wait_for(ConnectionStatus::Connected).await;
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+        assert_eq!(client.status(), ConnectionStatus::Closed);
+
+        let seen = seen.lock().unwrap().clone();
+        let events: Vec<_> = seen
+            .iter()
+            .map(|(event, status)| (event.as_str(), *status))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("connected", ConnectionStatus::Connected),
+                ("disconnected", ConnectionStatus::Connected),
+                (
+                    "reconnecting",
+                    ConnectionStatus::Reconnecting { attempt: 1 }
+                ),
+                ("connected", ConnectionStatus::Connected),
+                ("disconnected", ConnectionStatus::Connected),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn status_ends_failed_when_reconnects_run_out() {
+        let (listener, url) = listen().await;
+        drop(listener);
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("status_subject", "status"),
+                ("max_reconnect_attempts", "1"),
+                ("initial_reconnect_delay_ms", "10"),
+            ],
+        )));
+        let (run, seen) = run_recording_status(&client);
+        assert!(timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().is_err());
+        assert_eq!(client.status(), ConnectionStatus::Failed);
+
+        let seen = seen.lock().unwrap().clone();
+        assert_eq!(
+            seen,
+            [(
+                "reconnecting".to_string(),
+                ConnectionStatus::Reconnecting { attempt: 1 }
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn hooks_fire_in_order_around_each_connection() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("a".into())).await.unwrap();
+            ws.close(None).await.unwrap();
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("b".into())).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("initial_reconnect_delay_ms", "10"),
+                ("reconnect_jitter_pct", "0"),
+            ],
+        )));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            let (on_message, on_connect, on_disconnect) = (log.clone(), log.clone(), log.clone());
+            async move {
+                client
+                    .run_with_hooks(
+                        move |message| {
+                            on_message
+                                .lock()
+                                .unwrap()
+                                .push(format!("message {}", text(&message)));
+                            tx.send(())?;
+                            Ok(())
+                        },
+                        move || on_connect.lock().unwrap().push("connect".to_string()),
+                        move |_| on_disconnect.lock().unwrap().push("disconnect".to_string()),
+                    )
+                    .await
+            }
+        });
+        for _ in 0..2 {
+            timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+        }
+
+        // A drain ends the connection cleanly, so it is not reported as a disconnect
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["connect", "message a", "disconnect", "connect", "message b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn receive_messages_stops_at_the_count() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            assert_eq!(
+                ws.next().await.unwrap().unwrap(),
+                Message::Text("subscribe".into())
+            );
+            for text in ["one", "two", "three"] {
+                ws.send(Message::Text(text.into())).await.unwrap();
+            }
+            ws.send(Message::Binary(vec![1, 2])).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let config = config(&url, &[("on_connect_send", "subscribe")]);
+        let messages = receive_messages(config, 2, TEST_TIMEOUT).await.unwrap();
+        let texts: Vec<_> = messages.iter().map(text).collect();
+        assert_eq!(texts, ["one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn receive_messages_returns_early_on_close_or_timeout() {
+        let url = send_and_close("only", "done").await;
+        let messages = receive_messages(config(&url, &[]), 5, TEST_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            while ws.next().await.is_some() {}
+        });
+        let messages = receive_messages(config(&url, &[]), 5, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn batches_are_forwarded_when_full_or_flushed() {
+        let client = WebSocketClient::new(config("ws://feed.example.com", &[("batch_size", "2")]));
+        let mut forwarded = Vec::new();
+        let mut handler = |message: WebSocketMessage| {
+            forwarded.push(serde_json::from_slice::<Vec<serde_json::Value>>(
+                &message.payload,
+            )?);
+            Ok(())
+        };
+
+        client
+            .deliver(WebSocketMessage::text("a"), &mut handler)
+            .unwrap();
+        assert!(client.lock_batch().deadline.is_some());
+        client
+            .deliver(WebSocketMessage::text("b"), &mut handler)
+            .unwrap();
+        assert!(client.lock_batch().deadline.is_none());
+        client
+            .deliver(WebSocketMessage::binary(vec![0xff, 0x00]), &mut handler)
+            .unwrap();
+        client.flush_batch(&mut handler).unwrap();
+        // Nothing pending, so nothing is forwarded
+        client.flush_batch(&mut handler).unwrap();
+
+        assert_eq!(forwarded.len(), 2);
+        assert_eq!(forwarded[0][0]["payload"], "a");
+        assert_eq!(forwarded[0][1]["payload"], "b");
+        assert!(forwarded[0][0].get("payload_encoding").is_none());
+        assert_eq!(forwarded[1][0]["payload"], "/wA=");
+        assert_eq!(forwarded[1][0]["payload_encoding"], "base64");
+        assert_eq!(forwarded[1][0]["message_type"], "binary");
+    }
+
+    #[tokio::test]
+    async fn a_partial_batch_is_forwarded_after_the_batch_timeout() {
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            ws.send(Message::Text("a".into())).await.unwrap();
+            let _ = ws.next().await;
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[("batch_size", "10"), ("batch_timeout_ms", "1000")],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        timeout(TEST_TIMEOUT, async {
+            while client.lock_batch().messages.is_empty() {
+                sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // The deadline was set when the message arrived, before time stopped
+        tokio::time::pause();
+        tokio::time::advance(Duration::from_millis(900)).await;
+        assert!(rx.try_recv().is_err());
+        tokio::time::advance(Duration::from_millis(100)).await;
+        let batch = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&batch.payload).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["payload"], "a");
+    }
+
+    #[tokio::test]
+    async fn delayed_frames_do_not_hold_up_outbound_frames() {
+        let (listener, url) = listen().await;
+        let (sent_tx, sent_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            for payload in ["a", "b"] {
+                ws.send(Message::Text(payload.into())).await.unwrap();
+            }
+            if let Some(Ok(frame)) = ws.next().await {
+                let _ = sent_tx.send(frame);
+            }
+            let _ = ws.next().await;
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[
+                ("max_messages_per_sec", "1"),
+                ("rate_limit_policy", "delay"),
+            ],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        let first = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(text(&first), "a");
+
+        // "b" waits about a second for a token, but the send goes out meanwhile
+        client.send_message(b"x".to_vec(), false).unwrap();
+        let sent = timeout(Duration::from_millis(500), sent_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sent, Message::Text("x".into()));
+        assert!(rx.try_recv().is_err());
+
+        let second = timeout(TEST_TIMEOUT, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(text(&second), "b");
+        assert_eq!(client.metrics().messages_throttled, 1);
+
+        client.drain();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+    }
+
+    #[test]
+    fn frames_over_the_segment_cap_are_dropped_whole() {
+        let client = WebSocketClient::new(config(
+            "ws://feed.example.com",
+            &[("split_delimiter", "\n"), ("max_segments_per_frame", "2")],
+        ));
+        let mut forwarded = Vec::new();
+        let mut handler = |message: WebSocketMessage| {
+            forwarded.push(text(&message).to_string());
+            Ok(())
+        };
+
+        // Blank segments are skipped before the cap applies
+        client
+            .forward_segments("a\n\nb\n", "\n", &mut handler)
+            .unwrap();
+        client
+            .forward_segments("c\nd\ne", "\n", &mut handler)
+            .unwrap();
+        assert_eq!(forwarded, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn drain_flushes_the_batch_and_forwards_nothing_new() {
+        let (listener, url) = listen().await;
+        let (go_tx, go_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            for payload in ["a", "b"] {
+                ws.send(Message::Text(payload.into())).await.unwrap();
+            }
+            go_rx.await.unwrap();
+            // Sent after the drain started, so it must not be forwarded
+            let _ = ws.send(Message::Text("late".into())).await;
+            loop {
+                match ws.next().await {
+                    Some(Ok(Message::Close(_))) => return true,
+                    Some(Ok(_)) => {}
+                    _ => return false,
+                }
+            }
+        });
+
+        let client = Arc::new(WebSocketClient::new(config(
+            &url,
+            &[("batch_size", "10"), ("batch_timeout_ms", "60000")],
+        )));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let run = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .run(move |message| {
+                        tx.send(message)?;
+                        Ok(())
+                    })
+                    .await
+            }
+        });
+        timeout(TEST_TIMEOUT, async {
+            while client.lock_batch().messages.len() < 2 {
+                sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        client.drain();
+        go_tx.send(()).unwrap();
+        timeout(TEST_TIMEOUT, run).await.unwrap().unwrap().unwrap();
+        assert!(timeout(TEST_TIMEOUT, server).await.unwrap().unwrap());
+
+        let batch = rx.recv().await.unwrap();
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&batch.payload).unwrap();
+        let payloads: Vec<_> = entries.iter().map(|entry| &entry["payload"]).collect();
+        assert_eq!(payloads, ["a", "b"]);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn enrich_metadata_is_embedded_in_json_bodies_only() {
+        let client = WebSocketClient::new(config(
+            "ws://feed.example.com",
+            &[("enrich_metadata", "true")],
+        ));
+        let mut forwarded = Vec::new();
+        let mut handler = |mut message: WebSocketMessage| {
+            message.embed_connection();
+            forwarded.push(message);
+            Ok(())
+        };
+        client
+            .forward(WebSocketMessage::text(r#"{"price": 1}"#), &mut handler)
+            .unwrap();
+        client
+            .forward(WebSocketMessage::text("plain"), &mut handler)
+            .unwrap();
+        client
+            .forward(WebSocketMessage::text(r#"{"price": 2}"#), &mut handler)
+            .unwrap();
+
+        let first: serde_json::Value = serde_json::from_slice(&forwarded[0].payload).unwrap();
+        assert_eq!(first["price"], 1);
+        assert_eq!(first["connection"]["source_url"], "ws://feed.example.com");
+        assert_eq!(first["connection"]["sequence"], 1);
+
+        // The metadata is dropped, but the message still takes a sequence number
+        assert_eq!(text(&forwarded[1]), "plain");
+        assert!(forwarded[1].connection.is_none());
+        let third: serde_json::Value = serde_json::from_slice(&forwarded[2].payload).unwrap();
+        assert_eq!(third["connection"]["sequence"], 3);
+    }
+
+    #[tokio::test]
+    async fn request_takes_the_next_message_as_its_reply() {
+        let client = Arc::new(WebSocketClient::new(config(
+            "ws://feed.example.com",
+            &[("request_reply_mode", "true")],
+        )));
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move { client.request(b"ping".to_vec(), false, TEST_TIMEOUT).await }
+        });
+        while client.lock_reply_waiter().is_none() {
+            tokio::task::yield_now().await;
+        }
+
+        let mut forwarded = Vec::new();
+        let mut handler = |message| {
+            forwarded.push(message);
+            Ok(())
+        };
+        client
+            .forward(WebSocketMessage::text("pong"), &mut handler)
+            .unwrap();
+        client
+            .forward(WebSocketMessage::text("tick"), &mut handler)
+            .unwrap();
+
+        let reply = request.await.unwrap().unwrap();
+        assert_eq!(text(&reply), "pong");
+        assert_eq!(forwarded.iter().map(text).collect::<Vec<_>>(), ["tick"]);
+        assert_eq!(
+            client.outbound_rx.lock().await.try_recv().unwrap(),
+            Message::Text("ping".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn request_times_out_without_a_reply() {
+        let client = WebSocketClient::new(config("ws://feed.example.com", &[]));
+        let err = client
+            .request(b"ping".to_vec(), false, Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProviderError::Timeout { .. }));
+        assert!(client.lock_reply_waiter().is_none());
+    }
+
+    #[tokio::test]
+    async fn send_message_rejects_text_that_is_not_utf8() {
+        let client = WebSocketClient::new(config("ws://feed.example.com", &[]));
+        let err = client.send_message(vec![0xff, 0xfe], false).unwrap_err();
+        assert!(matches!(err, ProviderError::InvalidText(_)));
+
+        client.send_message(vec![0xff, 0xfe], true).unwrap();
+        client
+            .send_message("héllo".as_bytes().to_vec(), false)
+            .unwrap();
+        let mut outbound = client.outbound_rx.lock().await;
+        assert_eq!(
+            outbound.try_recv().unwrap(),
+            Message::Binary(vec![0xff, 0xfe])
+        );
+        assert_eq!(outbound.try_recv().unwrap(), Message::Text("héllo".into()));
+    }
+
+    #[tokio::test]
+    async fn send_message_rejects_frames_beyond_the_queue() {
+        let client = WebSocketClient::new(config("ws://feed.example.com", &[]));
+        for _ in 0..OUTBOUND_QUEUE_SIZE {
+            client.send_message(b"x".to_vec(), false).unwrap();
+        }
+        assert!(matches!(
+            client.send_message(b"x".to_vec(), false),
+            Err(ProviderError::OutboundQueueFull)
+        ));
+    }
+
+    #[tokio::test]
+    async fn protocol_violations_are_counted_and_reported() {
+        use tokio::io::AsyncWriteExt;
+
+        let (listener, url) = listen().await;
+        tokio::spawn(async move {
+            let mut ws = accept(&listener).await;
+            // A final frame with the reserved data opcode 0x3
+            ws.get_mut().write_all(&[0x83, 0x00]).await.unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let client = WebSocketClient::new(config(&url, &[]));
+        let err = client.connect_once(|_| Ok(())).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProviderError>(),
+            Some(ProviderError::ProtocolViolation(_))
+        ));
+        assert_eq!(client.protocol_violations_total(), 1);
+    }
 }